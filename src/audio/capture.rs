@@ -1,78 +1,513 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Sample, SampleFormat, Stream, StreamConfig};
-use std::sync::Arc;
+use super::device::{self, DeviceSelection, Endpoint, StreamPreferences};
+use super::downmix::{ChannelMix, Downmixer};
+use super::health::{Backoff, CaptureFault};
+use super::source::{AudioFrame, AudioSource, FrameFormat, FrameSender};
+use crate::state::{AppState, StateManager};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{
+    Device, FromSample, Host, SampleFormat, SizedSample, Stream, StreamConfig, StreamError,
+    SupportedStreamConfig,
+};
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
 use tokio::sync::mpsc;
 
+// Device audio the ring buffer can hold before the worker is considered behind
+const RING_BUFFER_MS: u32 = 500;
+// How often the worker drains the ring buffer
+const WORKER_POLL_INTERVAL: Duration = Duration::from_millis(5);
+// How often the supervisor checks that callbacks are still arriving
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+// How often the supervisor checks for unplugged devices and default changes
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Backoff between attempts to reopen a failed microphone
+const REOPEN_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const REOPEN_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Counters written by the real-time callback and read by the worker
+#[derive(Default)]
+pub struct CaptureStats {
+    /// Callbacks received, used to detect a stalled stream
+    pub callbacks: AtomicU64,
+    /// Callbacks that found the ring buffer too full for all of their data
+    pub overruns: AtomicU64,
+    /// Interleaved samples discarded because of overruns
+    pub dropped_samples: AtomicU64,
+}
+
+/// Keeps a capture stream open on the selected device, rebuilding it when
+/// the device disappears, errors or stalls or, when following the default,
+/// the default changes
 pub struct AudioCapture {
-    _stream: Stream,
+    format: FrameFormat,
+    selection: DeviceSelection,
+    endpoint: Endpoint,
+    preferences: StreamPreferences,
+    channel_mix: ChannelMix,
+    stall_timeout: Duration,
+    state_manager: Option<Arc<StateManager>>,
+    running: Arc<AtomicBool>,
+    supervisor: Option<JoinHandle<()>>,
 }
 
 impl AudioCapture {
-    pub fn start(tx: mpsc::UnboundedSender<Vec<i16>>) -> Result<Self, Box<dyn std::error::Error>> {
-        let host = cpal::default_host();
+    /// With a `state_manager`, the capture reports the device's availability
+    /// as the app state; without one it fails and recovers silently
+    pub fn new(
+        format: FrameFormat,
+        selection: DeviceSelection,
+        preferences: StreamPreferences,
+        channel_mix: ChannelMix,
+        stall_timeout: Duration,
+        state_manager: Option<Arc<StateManager>>,
+    ) -> Self {
+        Self {
+            format,
+            selection,
+            endpoint: Endpoint::Input,
+            preferences,
+            channel_mix,
+            stall_timeout,
+            state_manager,
+            running: Arc::new(AtomicBool::new(true)),
+            supervisor: None,
+        }
+    }
+
+    /// Record what an output device plays instead of an input device
+    pub fn loopback(mut self) -> Self {
+        self.endpoint = Endpoint::Loopback;
+        self
+    }
+}
+
+impl AudioSource for AudioCapture {
+    fn describe(&self) -> String {
+        let kind = match self.endpoint {
+            Endpoint::Input => "microphone",
+            Endpoint::Loopback => "output loopback",
+        };
+        match &self.selection {
+            DeviceSelection::SystemDefault => format!("{} (system default, {} channel mix)", kind, self.channel_mix),
+            DeviceSelection::Named(name) => format!("{} matching \"{}\" ({} channel mix)", kind, name, self.channel_mix),
+        }
+    }
+
+    fn format(&self) -> FrameFormat {
+        self.format
+    }
+
+    fn start(&mut self, tx: mpsc::UnboundedSender<AudioFrame>) -> Result<(), Box<dyn std::error::Error>> {
+        let supervisor = CaptureSupervisor {
+            host: cpal::default_host(),
+            selection: self.selection.clone(),
+            endpoint: self.endpoint,
+            preferences: self.preferences.clone(),
+            channel_mix: self.channel_mix,
+            format: self.format,
+            stall_timeout: self.stall_timeout,
+            tx,
+            position: Arc::new(AtomicU64::new(0)),
+            state_manager: self.state_manager.clone(),
+            runtime: Handle::current(),
+            running: self.running.clone(),
+        };
+
+        // cpal streams are not Send, so each one lives on the supervisor thread
+        self.supervisor = Some(
+            std::thread::Builder::new()
+                .name("audio-supervisor".to_string())
+                .spawn(move || supervisor.run())?,
+        );
+
+        Ok(())
+    }
+}
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(supervisor) = self.supervisor.take() {
+            let _ = supervisor.join();
+        }
+    }
+}
+
+struct CaptureSupervisor {
+    host: Host,
+    selection: DeviceSelection,
+    endpoint: Endpoint,
+    preferences: StreamPreferences,
+    channel_mix: ChannelMix,
+    format: FrameFormat,
+    stall_timeout: Duration,
+    tx: mpsc::UnboundedSender<AudioFrame>,
+    // Samples delivered across all sessions, so timestamps survive reopening
+    position: Arc<AtomicU64>,
+    state_manager: Option<Arc<StateManager>>,
+    runtime: Handle,
+    running: Arc<AtomicBool>,
+}
+
+/// Why the supervisor stopped watching a session
+enum SessionEnd {
+    Shutdown,
+    DefaultChanged,
+    Fault(CaptureFault),
+}
+
+impl CaptureSupervisor {
+    fn run(self) {
+        let mut backoff = Backoff::new(REOPEN_INITIAL_DELAY, REOPEN_MAX_DELAY);
 
-        // Get default INPUT device (microphone)
-        let device = host
-            .default_input_device()
-            .ok_or("No microphone found")?;
+        while self.is_running() {
+            let end = match self.open_session() {
+                Ok(session) => self.monitor(&session, &mut backoff),
+                Err(e) => SessionEnd::Fault(CaptureFault::from_error(&e.to_string())),
+            };
 
-        println!("[Audio] Using device: {}", device.name()?);
+            match end {
+                SessionEnd::Shutdown => break,
+                SessionEnd::DefaultChanged => println!("[Audio] Switching to the new default input..."),
+                SessionEnd::Fault(fault) => {
+                    eprintln!("[Audio] {}", fault);
+                    self.set_available(false);
+
+                    let delay = backoff.next_delay();
+                    println!("[Audio] Reopening microphone in {} ms...", delay.as_millis());
+                    self.sleep(delay);
+                }
+            }
+        }
+    }
+
+    fn open_session(&self) -> Result<CaptureSession, Box<dyn std::error::Error>> {
+        let device = self.selection.find(&self.host, self.endpoint)?;
+        CaptureSession::open(
+            &device,
+            self.endpoint,
+            &self.preferences,
+            self.channel_mix,
+            self.format,
+            self.tx.clone(),
+            self.position.clone(),
+        )
+    }
 
-        let config = device.default_input_config()?;
+    /// Watch a session until it fails, stalls or should be replaced.
+    /// The microphone only counts as available once callbacks arrive.
+    fn monitor(&self, session: &CaptureSession, backoff: &mut Backoff) -> SessionEnd {
+        let mut flowing = false;
+        let mut last_callbacks = 0;
+        let mut last_progress = Instant::now();
+        let mut last_device_check = Instant::now();
+
+        loop {
+            self.sleep(HEALTH_POLL_INTERVAL);
+            if !self.is_running() {
+                return SessionEnd::Shutdown;
+            }
+
+            if let Some(fault) = session.fault.lock().unwrap().take() {
+                return SessionEnd::Fault(fault);
+            }
+
+            let callbacks = session.stats.callbacks.load(Ordering::Relaxed);
+            if callbacks != last_callbacks {
+                last_callbacks = callbacks;
+                last_progress = Instant::now();
+
+                if !flowing {
+                    flowing = true;
+                    backoff.reset();
+                    self.set_available(true);
+                }
+            } else if self.endpoint == Endpoint::Input && last_progress.elapsed() >= self.stall_timeout {
+                // Loopback delivers nothing while the output is silent, so
+                // only an input can be considered stalled
+                return SessionEnd::Fault(CaptureFault::Stalled(last_progress.elapsed()));
+            }
+
+            if last_device_check.elapsed() < DEVICE_POLL_INTERVAL {
+                continue;
+            }
+            last_device_check = Instant::now();
+
+            if !device::is_present(&self.host, self.endpoint, &session.device_name) {
+                return SessionEnd::Fault(CaptureFault::DeviceMissing(format!(
+                    "{} was disconnected",
+                    session.device_name
+                )));
+            }
+
+            if self.selection == DeviceSelection::SystemDefault {
+                if let Some(default_name) = device::default_name(&self.host, self.endpoint) {
+                    if default_name != session.device_name {
+                        println!("[Audio] Default device changed to: {}", default_name);
+                        return SessionEnd::DefaultChanged;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reflect microphone availability in the app state, leaving a user pause alone
+    fn set_available(&self, available: bool) {
+        let Some(state_manager) = self.state_manager.clone() else {
+            return;
+        };
+        self.runtime.block_on(async move {
+            let current = state_manager.get().await;
+            if available && current == AppState::MicConflict {
+                state_manager.set(AppState::Active).await;
+            } else if !available && current != AppState::Paused {
+                state_manager.set(AppState::MicConflict).await;
+            }
+        });
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed) && !self.tx.is_closed()
+    }
+
+    // Sleep in short steps so shutdown is not delayed by a long interval
+    fn sleep(&self, duration: Duration) {
+        let step = Duration::from_millis(100);
+        let mut remaining = duration;
+        while !remaining.is_zero() && self.is_running() {
+            let nap = remaining.min(step);
+            std::thread::sleep(nap);
+            remaining -= nap;
+        }
+    }
+}
+
+/// One open stream on one device, with the worker that drains it
+struct CaptureSession {
+    _stream: Stream,
+    device_name: String,
+    stats: Arc<CaptureStats>,
+    fault: Arc<Mutex<Option<CaptureFault>>>,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl CaptureSession {
+    /// Open the device with the first candidate config that builds
+    fn open(
+        device: &Device,
+        endpoint: Endpoint,
+        preferences: &StreamPreferences,
+        channel_mix: ChannelMix,
+        format: FrameFormat,
+        tx: mpsc::UnboundedSender<AudioFrame>,
+        position: Arc<AtomicU64>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let device_name = device.name()?;
+        println!("[Audio] Using device: {}", device_name);
+
+        let mut last_error: Box<dyn std::error::Error> = "Device reports no supported configs".into();
+        for config in device::candidate_configs(device, endpoint, preferences, format.sample_rate) {
+            let opened = Self::open_with_config(
+                device,
+                &device_name,
+                &config,
+                channel_mix,
+                format,
+                tx.clone(),
+                position.clone(),
+            );
+            match opened {
+                Ok(session) => return Ok(session),
+                Err(e) => {
+                    eprintln!("[Audio] Config {:?} failed: {}", config, e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    fn open_with_config(
+        device: &Device,
+        device_name: &str,
+        config: &SupportedStreamConfig,
+        channel_mix: ChannelMix,
+        format: FrameFormat,
+        tx: mpsc::UnboundedSender<AudioFrame>,
+        position: Arc<AtomicU64>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         println!("[Audio] Config: {:?}", config);
 
+        let device_rate = config.sample_rate().0;
+        let channels = config.channels() as usize;
+
+        // Loudest re-chooses its channel once per pipeline frame
+        let window = (device_rate as u64 * format.frame_ms as u64 / 1000) as usize;
+        let downmixer = Downmixer::new(channel_mix, channels, window)?;
+        println!("[Audio] Channel mix: {} of {} channel(s)", channel_mix, channels);
+
+        // Whole frames only, so a frame never straddles the wrap-around point
+        let frames = (device_rate * RING_BUFFER_MS / 1000) as usize;
+        let (producer, consumer) = RingBuffer::<i16>::new(frames * channels);
+        let stats = Arc::new(CaptureStats::default());
+        let fault = Arc::new(Mutex::new(None));
+
         // Build stream based on sample format
+        let stream_config: StreamConfig = config.clone().into();
+        let (s, f) = (stats.clone(), fault.clone());
         let stream = match config.sample_format() {
-            SampleFormat::F32 => Self::build_stream::<f32>(&device, &config.into(), tx)?,
-            SampleFormat::I16 => Self::build_stream::<i16>(&device, &config.into(), tx)?,
-            SampleFormat::U16 => Self::build_stream::<u16>(&device, &config.into(), tx)?,
+            SampleFormat::I8 => Self::build_stream::<i8>(device, &stream_config, producer, s, f)?,
+            SampleFormat::I16 => Self::build_stream::<i16>(device, &stream_config, producer, s, f)?,
+            SampleFormat::I32 => Self::build_stream::<i32>(device, &stream_config, producer, s, f)?,
+            SampleFormat::I64 => Self::build_stream::<i64>(device, &stream_config, producer, s, f)?,
+            SampleFormat::U8 => Self::build_stream::<u8>(device, &stream_config, producer, s, f)?,
+            SampleFormat::U16 => Self::build_stream::<u16>(device, &stream_config, producer, s, f)?,
+            SampleFormat::U32 => Self::build_stream::<u32>(device, &stream_config, producer, s, f)?,
+            SampleFormat::U64 => Self::build_stream::<u64>(device, &stream_config, producer, s, f)?,
+            SampleFormat::F32 => Self::build_stream::<f32>(device, &stream_config, producer, s, f)?,
+            SampleFormat::F64 => Self::build_stream::<f64>(device, &stream_config, producer, s, f)?,
             format => return Err(format!("Unsupported sample format: {:?}", format).into()),
         };
 
+        let running = Arc::new(AtomicBool::new(true));
+        let worker = CaptureWorker {
+            consumer,
+            channels,
+            downmixer,
+            mono: Vec::with_capacity(frames),
+            sender: FrameSender::new(device_rate, format, tx).starting_at(position.load(Ordering::Relaxed)),
+            position,
+            stats: stats.clone(),
+            running: running.clone(),
+        };
+        let worker = std::thread::Builder::new()
+            .name("audio-capture".to_string())
+            .spawn(move || worker.run())?;
+
         stream.play()?;
 
-        Ok(Self { _stream: stream })
+        Ok(Self {
+            _stream: stream,
+            device_name: device_name.to_string(),
+            stats,
+            fault,
+            running,
+            worker: Some(worker),
+        })
     }
 
     fn build_stream<T>(
         device: &Device,
         config: &StreamConfig,
-        tx: mpsc::UnboundedSender<Vec<i16>>,
+        mut producer: Producer<i16>,
+        stats: Arc<CaptureStats>,
+        fault: Arc<Mutex<Option<CaptureFault>>>,
     ) -> Result<Stream, Box<dyn std::error::Error>>
     where
-        T: Sample + cpal::SizedSample,
+        T: SizedSample,
+        i16: FromSample<T>,
     {
         let channels = config.channels as usize;
-        let tx = Arc::new(tx);
 
+        // Runs on the real-time audio thread: no allocation, no locks, no blocking
         let stream = device.build_input_stream(
             config,
             move |data: &[T], _: &cpal::InputCallbackInfo| {
-                // Convert to mono i16
-                let mono: Vec<i16> = data
-                    .chunks(channels)
-                    .map(|frame| {
-                        // Average channels to mono
-                        let sum: i32 = frame.iter().map(|&s| Self::to_i16(s) as i32).sum();
-                        (sum / channels as i32) as i16
-                    })
-                    .collect();
-
-                // Send in chunks of ~100ms (1600 samples at 16kHz)
-                for chunk in mono.chunks(1600) {
-                    let _ = tx.send(chunk.to_vec());
+                stats.callbacks.fetch_add(1, Ordering::Relaxed);
+
+                let writable = producer.slots().min(data.len()) / channels * channels;
+                if let Ok(chunk) = producer.write_chunk_uninit(writable) {
+                    chunk.fill_from_iter(data.iter().map(|&s| s.to_sample::<i16>()));
+                }
+
+                let dropped = data.len() - writable;
+                if dropped > 0 {
+                    stats.overruns.fetch_add(1, Ordering::Relaxed);
+                    stats.dropped_samples.fetch_add(dropped as u64, Ordering::Relaxed);
                 }
             },
+            // Errors are reported on a non-real-time thread, so locking is fine
             move |err| {
-                eprintln!("[Audio] Error: {}", err);
+                let reported = match err {
+                    StreamError::DeviceNotAvailable => CaptureFault::DeviceMissing(err.to_string()),
+                    StreamError::BackendSpecific { .. } => CaptureFault::from_error(&err.to_string()),
+                };
+                *fault.lock().unwrap() = Some(reported);
             },
             None,
         )?;
 
         Ok(stream)
     }
+}
+
+impl Drop for CaptureSession {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Drains the ring buffer off the audio thread: downmixes to mono,
+/// resamples to the pipeline rate and delivers fixed-duration frames
+struct CaptureWorker {
+    consumer: Consumer<i16>,
+    channels: usize,
+    downmixer: Downmixer,
+    mono: Vec<i16>,
+    sender: FrameSender,
+    position: Arc<AtomicU64>,
+    stats: Arc<CaptureStats>,
+    running: Arc<AtomicBool>,
+}
+
+impl CaptureWorker {
+    fn run(mut self) {
+        let mut reported_overruns = 0;
+
+        while self.running.load(Ordering::Relaxed) && !self.sender.is_closed() {
+            self.drain();
+
+            let overruns = self.stats.overruns.load(Ordering::Relaxed);
+            if overruns != reported_overruns {
+                eprintln!(
+                    "[Audio] Ring buffer overrun ({} total, {} samples dropped)",
+                    overruns,
+                    self.stats.dropped_samples.load(Ordering::Relaxed)
+                );
+                reported_overruns = overruns;
+            }
+
+            std::thread::sleep(WORKER_POLL_INTERVAL);
+        }
+    }
+
+    fn drain(&mut self) {
+        let available = self.consumer.slots() / self.channels * self.channels;
+        if available == 0 {
+            return;
+        }
+
+        let chunk = match self.consumer.read_chunk(available) {
+            Ok(chunk) => chunk,
+            Err(_) => return,
+        };
+
+        self.mono.clear();
+        let (first, second) = chunk.as_slices();
+        for part in [first, second] {
+            self.downmixer.process(part, &mut self.mono);
+        }
+        chunk.commit_all();
 
-    fn to_i16<T: Sample>(sample: T) -> i16 {
-        sample.to_i16()
+        self.sender.push(&self.mono);
+        self.position.store(self.sender.position(), Ordering::Relaxed);
     }
 }
@@ -0,0 +1,319 @@
+use crate::config::AudioConfig;
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{Device, Host, SampleFormat, SampleRate, SupportedStreamConfig, SupportedStreamConfigRange};
+
+/// Whether a capture records an input device or what an output device plays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endpoint {
+    Input,
+    /// Opening an output device for input records its playback on WASAPI
+    Loopback,
+}
+
+impl Endpoint {
+    fn devices(self, host: &Host) -> Result<Vec<Device>, cpal::DevicesError> {
+        Ok(match self {
+            Endpoint::Input => host.input_devices()?.collect(),
+            Endpoint::Loopback => host.output_devices()?.collect(),
+        })
+    }
+
+    fn default_device(self, host: &Host) -> Option<Device> {
+        match self {
+            Endpoint::Input => host.default_input_device(),
+            Endpoint::Loopback => host.default_output_device(),
+        }
+    }
+}
+
+/// Which input device the capture supervisor should open
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceSelection {
+    /// Follow the system default input, switching when it changes
+    SystemDefault,
+    /// Exact name, or failing that a case-insensitive substring of the name
+    Named(String),
+}
+
+impl DeviceSelection {
+    /// Unset, empty and "default" all mean following the system default
+    pub fn from_config(device: Option<&str>) -> Self {
+        match device.map(str::trim) {
+            None | Some("") => DeviceSelection::SystemDefault,
+            Some(name) if name.eq_ignore_ascii_case("default") => DeviceSelection::SystemDefault,
+            Some(name) => DeviceSelection::Named(name.to_string()),
+        }
+    }
+
+    pub fn find(&self, host: &Host, endpoint: Endpoint) -> Result<Device, Box<dyn std::error::Error>> {
+        match self {
+            DeviceSelection::SystemDefault => endpoint.default_device(host).ok_or_else(|| match endpoint {
+                Endpoint::Input => "No microphone found".into(),
+                Endpoint::Loopback => "No output device found".into(),
+            }),
+            DeviceSelection::Named(wanted) => {
+                let mut devices: Vec<(String, Device)> = endpoint
+                    .devices(host)?
+                    .into_iter()
+                    .filter_map(|device| device.name().ok().map(|name| (name, device)))
+                    .collect();
+
+                let names: Vec<&str> = devices.iter().map(|(name, _)| name.as_str()).collect();
+                let index = match_name(&names, wanted).ok_or_else(|| match endpoint {
+                    Endpoint::Input => format!("No input device matching \"{}\"", wanted),
+                    Endpoint::Loopback => format!("No output device matching \"{}\"", wanted),
+                })?;
+
+                Ok(devices.swap_remove(index).1)
+            }
+        }
+    }
+}
+
+/// Index of the device called `wanted`, or failing that of the first whose
+/// name contains it, ignoring case
+fn match_name(names: &[&str], wanted: &str) -> Option<usize> {
+    let wanted_lower = wanted.to_lowercase();
+    names
+        .iter()
+        .position(|&name| name == wanted)
+        .or_else(|| names.iter().position(|name| name.to_lowercase().contains(&wanted_lower)))
+}
+
+pub struct DeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+}
+
+pub fn list_input_devices() -> Result<Vec<DeviceInfo>, Box<dyn std::error::Error>> {
+    let host = cpal::default_host();
+    let default_name = default_name(&host, Endpoint::Input);
+
+    let devices = host
+        .input_devices()?
+        .filter_map(|device| device.name().ok())
+        .map(|name| DeviceInfo {
+            is_default: Some(&name) == default_name.as_ref(),
+            name,
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+pub fn default_name(host: &Host, endpoint: Endpoint) -> Option<String> {
+    endpoint.default_device(host).and_then(|device| device.name().ok())
+}
+
+pub fn is_present(host: &Host, endpoint: Endpoint, name: &str) -> bool {
+    match endpoint.devices(host) {
+        Ok(devices) => devices.iter().any(|device| device.name().is_ok_and(|n| n == name)),
+        // Enumeration can fail transiently; only a definite absence counts
+        Err(_) => true,
+    }
+}
+
+/// Stream settings requested for the input device; unset fields are free
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamPreferences {
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub format: Option<SampleFormat>,
+}
+
+impl StreamPreferences {
+    pub fn from_config(audio: &AudioConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let format = match audio.device_format.as_deref() {
+            None => None,
+            Some(name) => Some(
+                parse_sample_format(name)
+                    .ok_or_else(|| format!("Unknown audio.device_format: {}", name))?,
+            ),
+        };
+
+        Ok(Self {
+            sample_rate: audio.device_sample_rate,
+            channels: audio.device_channels,
+            format,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.sample_rate.is_none() && self.channels.is_none() && self.format.is_none()
+    }
+
+    fn matches(&self, range: &SupportedStreamConfigRange) -> bool {
+        self.channels.is_none_or(|c| range.channels() == c)
+            && self.format.is_none_or(|f| range.sample_format() == f)
+            && self.sample_rate.is_none_or(|r| {
+                range.min_sample_rate().0 <= r && r <= range.max_sample_rate().0
+            })
+    }
+}
+
+pub fn parse_sample_format(name: &str) -> Option<SampleFormat> {
+    let format = match name.to_lowercase().as_str() {
+        "i8" => SampleFormat::I8,
+        "i16" => SampleFormat::I16,
+        "i32" => SampleFormat::I32,
+        "i64" => SampleFormat::I64,
+        "u8" => SampleFormat::U8,
+        "u16" => SampleFormat::U16,
+        "u32" => SampleFormat::U32,
+        "u64" => SampleFormat::U64,
+        "f32" => SampleFormat::F32,
+        "f64" => SampleFormat::F64,
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Stream configurations to try, best first: those matching the preferences,
+/// the device default, then every other supported configuration
+pub fn candidate_configs(
+    device: &Device,
+    endpoint: Endpoint,
+    preferences: &StreamPreferences,
+    target_rate: u32,
+) -> Vec<SupportedStreamConfig> {
+    let supported = match endpoint {
+        Endpoint::Input => device.supported_input_configs().map(|ranges| ranges.collect()),
+        Endpoint::Loopback => device.supported_output_configs().map(|ranges| ranges.collect()),
+    };
+    let ranges: Vec<SupportedStreamConfigRange> = match supported {
+        Ok(ranges) => ranges,
+        Err(e) => {
+            eprintln!("[Audio] Cannot query supported configs: {}", e);
+            Vec::new()
+        }
+    };
+    let default = match endpoint {
+        Endpoint::Input => device.default_input_config(),
+        Endpoint::Loopback => device.default_output_config(),
+    };
+    order_candidates(ranges, default.ok(), preferences, target_rate)
+}
+
+/// The ordering behind `candidate_configs`, given what the device reported
+fn order_candidates(
+    mut ranges: Vec<SupportedStreamConfigRange>,
+    default: Option<SupportedStreamConfig>,
+    preferences: &StreamPreferences,
+    target_rate: u32,
+) -> Vec<SupportedStreamConfig> {
+    // Ranges covering the pipeline rate avoid resampling; then fewer
+    // channels and the formats with the cheapest conversion
+    ranges.sort_by_key(|range| {
+        (
+            !(range.min_sample_rate().0 <= target_rate && target_rate <= range.max_sample_rate().0),
+            range.channels(),
+            format_rank(range.sample_format()),
+        )
+    });
+
+    let pick_rate = |range: &SupportedStreamConfigRange, wanted: u32| {
+        let rate = wanted.clamp(range.min_sample_rate().0, range.max_sample_rate().0);
+        range.with_sample_rate(SampleRate(rate))
+    };
+
+    let mut candidates = Vec::new();
+    if !preferences.is_empty() {
+        let wanted = preferences.sample_rate.unwrap_or(target_rate);
+        candidates.extend(
+            ranges
+                .iter()
+                .filter(|range| preferences.matches(range))
+                .map(|range| pick_rate(range, wanted)),
+        );
+        if candidates.is_empty() {
+            eprintln!("[Audio] No supported config matches {:?}", preferences);
+        }
+    }
+    candidates.extend(default);
+    candidates.extend(ranges.iter().map(|range| pick_rate(range, target_rate)));
+
+    let mut unique: Vec<SupportedStreamConfig> = Vec::with_capacity(candidates.len());
+    for candidate in candidates {
+        if !unique.contains(&candidate) {
+            unique.push(candidate);
+        }
+    }
+    unique
+}
+
+fn format_rank(format: SampleFormat) -> u8 {
+    match format {
+        SampleFormat::F32 => 0,
+        SampleFormat::I16 => 1,
+        SampleFormat::I32 => 2,
+        SampleFormat::F64 => 3,
+        SampleFormat::U16 => 4,
+        SampleFormat::I8 | SampleFormat::U8 => 5,
+        _ => 6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::SupportedBufferSize;
+
+    fn range(channels: u16, min: u32, max: u32, format: SampleFormat) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(channels, SampleRate(min), SampleRate(max), SupportedBufferSize::Unknown, format)
+    }
+
+    fn config(channels: u16, rate: u32, format: SampleFormat) -> SupportedStreamConfig {
+        SupportedStreamConfig::new(channels, SampleRate(rate), SupportedBufferSize::Unknown, format)
+    }
+
+    #[test]
+    fn matches_exact_names_before_substrings() {
+        let names = ["Microphone (USB Headset)", "USB Headset", "Line In"];
+        assert_eq!(match_name(&names, "USB Headset"), Some(1));
+        assert_eq!(match_name(&names, "usb headset"), Some(0));
+        assert_eq!(match_name(&names, "LINE"), Some(2));
+        assert_eq!(match_name(&names, "Webcam"), None);
+    }
+
+    #[test]
+    fn parses_sample_formats_in_any_case() {
+        assert_eq!(parse_sample_format("i16"), Some(SampleFormat::I16));
+        assert_eq!(parse_sample_format("F32"), Some(SampleFormat::F32));
+        assert_eq!(parse_sample_format("u64"), Some(SampleFormat::U64));
+        assert_eq!(parse_sample_format("s16le"), None);
+    }
+
+    #[test]
+    fn orders_preferences_then_default_then_cheapest() {
+        let ranges = vec![
+            range(2, 44100, 48000, SampleFormat::F32),
+            range(2, 8000, 48000, SampleFormat::I32),
+            range(1, 8000, 48000, SampleFormat::I16),
+            range(1, 8000, 48000, SampleFormat::F32),
+        ];
+        let default = config(2, 48000, SampleFormat::F32);
+
+        // Without preferences: the default, then ranges covering 16 kHz,
+        // mono first, then by conversion cost
+        let candidates = order_candidates(ranges.clone(), Some(default.clone()), &StreamPreferences::default(), 16000);
+        assert_eq!(
+            candidates,
+            [
+                default.clone(),
+                config(1, 16000, SampleFormat::F32),
+                config(1, 16000, SampleFormat::I16),
+                config(2, 16000, SampleFormat::I32),
+                config(2, 44100, SampleFormat::F32),
+            ]
+        );
+
+        // Matching preferences come first, at the preferred rate
+        let preferences = StreamPreferences {
+            sample_rate: Some(48000),
+            channels: Some(2),
+            format: None,
+        };
+        let candidates = order_candidates(ranges, Some(default.clone()), &preferences, 16000);
+        assert_eq!(candidates[..3], [config(2, 48000, SampleFormat::I32), default, config(1, 16000, SampleFormat::F32)]);
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt;
+
+/// How multi-channel input is reduced to the mono pipeline signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelMix {
+    /// Mean of all channels
+    Average,
+    /// The channel with the most energy, chosen again every window
+    Loudest,
+    /// A single channel, zero-based
+    Channel(usize),
+}
+
+impl ChannelMix {
+    /// "average", "loudest" or a 1-based channel number
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "average" => Ok(ChannelMix::Average),
+            "loudest" => Ok(ChannelMix::Loudest),
+            other => match other.parse::<usize>() {
+                Ok(n) if n >= 1 => Ok(ChannelMix::Channel(n - 1)),
+                _ => Err(format!(
+                    "Unknown audio.channel_mix: {} (use \"average\", \"loudest\" or a channel number from 1)",
+                    value
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ChannelMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelMix::Average => write!(f, "average"),
+            ChannelMix::Loudest => write!(f, "loudest"),
+            ChannelMix::Channel(index) => write!(f, "channel {}", index + 1),
+        }
+    }
+}
+
+/// Reduces interleaved frames to mono according to a `ChannelMix`
+pub struct Downmixer {
+    mix: ChannelMix,
+    channels: usize,
+    // Loudest only: frames per decision window, frames seen in the current
+    // window, per-channel energy so far and the channel being passed through
+    window: usize,
+    filled: usize,
+    energy: Vec<u64>,
+    current: usize,
+}
+
+impl Downmixer {
+    /// `window` is in frames. Loudest switches channel at window boundaries,
+    /// using the energy of the window that just ended, so it adds no latency.
+    pub fn new(mix: ChannelMix, channels: usize, window: usize) -> Result<Self, String> {
+        if let ChannelMix::Channel(index) = mix {
+            if index >= channels {
+                return Err(format!(
+                    "Channel {} requested but the input has {} channel(s)",
+                    index + 1,
+                    channels
+                ));
+            }
+        }
+
+        Ok(Self {
+            mix,
+            channels,
+            window: window.max(1),
+            filled: 0,
+            energy: vec![0; channels],
+            current: 0,
+        })
+    }
+
+    /// Append one mono sample per complete frame in `interleaved`
+    pub fn process(&mut self, interleaved: &[i16], mono: &mut Vec<i16>) {
+        if self.channels == 1 {
+            mono.extend_from_slice(interleaved);
+            return;
+        }
+
+        for frame in interleaved.chunks_exact(self.channels) {
+            match self.mix {
+                ChannelMix::Average => {
+                    let sum: i32 = frame.iter().map(|&s| s as i32).sum();
+                    mono.push((sum / self.channels as i32) as i16);
+                }
+                ChannelMix::Channel(index) => mono.push(frame[index]),
+                ChannelMix::Loudest => {
+                    mono.push(frame[self.current]);
+                    for (energy, &s) in self.energy.iter_mut().zip(frame) {
+                        *energy += (s as i64 * s as i64) as u64;
+                    }
+
+                    self.filled += 1;
+                    if self.filled == self.window {
+                        self.current = (0..self.channels).max_by_key(|&c| self.energy[c]).unwrap_or(0);
+                        self.energy.iter_mut().for_each(|e| *e = 0);
+                        self.filled = 0;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The samples of the channel with the most energy in an interleaved block
+pub fn loudest_channel(interleaved: &[i16], channels: usize) -> Vec<i16> {
+    let energy = |channel: usize| -> u64 {
+        interleaved
+            .iter()
+            .skip(channel)
+            .step_by(channels)
+            .map(|&s| (s as i64 * s as i64) as u64)
+            .sum()
+    };
+    let loudest = (0..channels).max_by_key(|&c| energy(c)).unwrap_or(0);
+    interleaved.iter().skip(loudest).step_by(channels).copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interleave(left: &[i16], right: &[i16]) -> Vec<i16> {
+        left.iter().zip(right).flat_map(|(&l, &r)| [l, r]).collect()
+    }
+
+    #[test]
+    fn parses_mix_names_and_channel_numbers() {
+        assert_eq!(ChannelMix::parse("average"), Ok(ChannelMix::Average));
+        assert_eq!(ChannelMix::parse("Loudest"), Ok(ChannelMix::Loudest));
+        assert_eq!(ChannelMix::parse("2"), Ok(ChannelMix::Channel(1)));
+        assert!(ChannelMix::parse("0").is_err());
+        assert!(ChannelMix::parse("left").is_err());
+    }
+
+    #[test]
+    fn average_and_single_channel() {
+        let stereo = interleave(&[1000, 2000], &[0, -2000]);
+
+        let mut mono = Vec::new();
+        Downmixer::new(ChannelMix::Average, 2, 1).unwrap().process(&stereo, &mut mono);
+        assert_eq!(mono, vec![500, 0]);
+
+        mono.clear();
+        Downmixer::new(ChannelMix::Channel(1), 2, 1).unwrap().process(&stereo, &mut mono);
+        assert_eq!(mono, vec![0, -2000]);
+
+        assert!(Downmixer::new(ChannelMix::Channel(2), 2, 1).is_err());
+    }
+
+    #[test]
+    fn loudest_follows_the_active_channel_per_window() {
+        // Speech moves from the right channel to the left one after 4 frames
+        let left = [0, 0, 0, 0, 9000, -9000, 9000, -9000, 9000, -9000, 9000, -9000];
+        let right = [5000, -5000, 5000, -5000, 10, -10, 10, -10, 10, -10, 10, -10];
+        let stereo = interleave(&left, &right);
+
+        let mut mono = Vec::new();
+        let mut downmixer = Downmixer::new(ChannelMix::Loudest, 2, 4).unwrap();
+        downmixer.process(&stereo, &mut mono);
+
+        // First window starts on channel 1, the decision lags one window
+        assert_eq!(&mono[..4], &[0, 0, 0, 0]);
+        assert_eq!(&mono[4..8], &right[4..8]);
+        assert_eq!(&mono[8..], &left[8..]);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::config::DspConfig;
+
+/// Optional preprocessing applied to every frame before VAD and upload:
+/// high-pass filter, then noise gate, then automatic gain control
+pub struct DspChain {
+    // One set of stages per channel of interleaved input, each with its own state
+    channels: Vec<Stages>,
+    buffer: Vec<f32>,
+}
+
+struct Stages {
+    high_pass: Option<HighPass>,
+    gate: Option<NoiseGate>,
+    agc: Option<Agc>,
+}
+
+impl DspChain {
+    pub fn new(config: &DspConfig, sample_rate: u32, channels: u16) -> Self {
+        let rate = sample_rate as f32;
+        let stages = || Stages {
+            high_pass: config.high_pass.then(|| HighPass::new(rate, config.high_pass_hz)),
+            gate: config.noise_gate.then(|| NoiseGate::new(rate, config.gate_threshold_db)),
+            agc: config.agc.then(|| Agc::new(rate, config.agc_target_db, config.agc_max_gain_db)),
+        };
+        Self {
+            channels: (0..channels.max(1)).map(|_| stages()).collect(),
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        let stages = &self.channels[0];
+        stages.high_pass.is_some() || stages.gate.is_some() || stages.agc.is_some()
+    }
+
+    /// Names of the enabled stages, for the startup log
+    pub fn describe(&self) -> String {
+        let first = &self.channels[0];
+        let stages: Vec<&str> = [
+            first.high_pass.as_ref().map(|_| "high-pass"),
+            first.gate.as_ref().map(|_| "noise gate"),
+            first.agc.as_ref().map(|_| "AGC"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if stages.is_empty() {
+            "off".to_string()
+        } else {
+            stages.join(" -> ")
+        }
+    }
+
+    /// Process a frame, interleaved when the chain has more than one channel
+    pub fn process(&mut self, samples: &mut [i16]) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let count = self.channels.len();
+        for (channel, stages) in self.channels.iter_mut().enumerate() {
+            self.buffer.clear();
+            self.buffer
+                .extend(samples.iter().skip(channel).step_by(count).map(|&s| s as f32 / 32768.0));
+
+            stages.process(&mut self.buffer);
+
+            for (out, &x) in samples.iter_mut().skip(channel).step_by(count).zip(&self.buffer) {
+                *out = (x * 32768.0).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+            }
+        }
+    }
+}
+
+impl Stages {
+    fn process(&mut self, buffer: &mut [f32]) {
+        if let Some(high_pass) = &mut self.high_pass {
+            high_pass.process(buffer);
+        }
+        if let Some(gate) = &mut self.gate {
+            gate.process(buffer);
+        }
+        if let Some(agc) = &mut self.agc {
+            agc.process(buffer);
+        }
+    }
+}
+
+fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// One-pole smoothing coefficient for a time constant in seconds
+fn smoothing(sample_rate: f32, seconds: f32) -> f32 {
+    (-1.0 / (seconds * sample_rate)).exp()
+}
+
+/// Fourth-order Butterworth high-pass (two biquads), removing DC offset
+/// and low-frequency hum below the speech band
+pub struct HighPass {
+    sections: [Biquad; 2],
+}
+
+impl HighPass {
+    pub fn new(sample_rate: f32, cutoff_hz: f32) -> Self {
+        // Q values of the two sections of a 4th-order Butterworth
+        Self {
+            sections: [
+                Biquad::high_pass(sample_rate, cutoff_hz, 0.541_196_1),
+                Biquad::high_pass(sample_rate, cutoff_hz, 1.306_563),
+            ],
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for section in &mut self.sections {
+            section.process(samples);
+        }
+    }
+}
+
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Biquad {
+    fn high_pass(sample_rate: f32, cutoff_hz: f32, q: f32) -> Self {
+        let w0 = 2.0 * std::f32::consts::PI * cutoff_hz / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b0: (1.0 + cos) / 2.0 / a0,
+            b1: -(1.0 + cos) / a0,
+            b2: (1.0 + cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let x = *sample;
+            let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2 - self.a1 * self.y1 - self.a2 * self.y2;
+            self.x2 = self.x1;
+            self.x1 = x;
+            self.y2 = self.y1;
+            self.y1 = y;
+            *sample = y;
+        }
+    }
+}
+
+/// Mutes the signal while its envelope stays under a threshold, holding the
+/// gate open briefly after speech so word endings are not clipped
+pub struct NoiseGate {
+    threshold: f32,
+    hold_samples: u32,
+    held: u32,
+    envelope: f32,
+    gain: f32,
+    attack: f32,
+    release: f32,
+    ramp: f32,
+}
+
+impl NoiseGate {
+    const HOLD_SECONDS: f32 = 0.15;
+
+    pub fn new(sample_rate: f32, threshold_db: f32) -> Self {
+        Self {
+            threshold: db_to_linear(threshold_db),
+            hold_samples: (Self::HOLD_SECONDS * sample_rate) as u32,
+            held: 0,
+            envelope: 0.0,
+            gain: 0.0,
+            attack: smoothing(sample_rate, 0.001),
+            release: smoothing(sample_rate, 0.05),
+            // Gain changes over ~5ms to avoid clicks
+            ramp: smoothing(sample_rate, 0.005),
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let level = sample.abs();
+            let coef = if level > self.envelope { self.attack } else { self.release };
+            self.envelope = level + coef * (self.envelope - level);
+
+            let open = if self.envelope >= self.threshold {
+                self.held = self.hold_samples;
+                true
+            } else if self.held > 0 {
+                self.held -= 1;
+                true
+            } else {
+                false
+            };
+
+            let target = if open { 1.0 } else { 0.0 };
+            self.gain = target + self.ramp * (self.gain - target);
+            *sample *= self.gain;
+        }
+    }
+}
+
+/// Automatic gain control: steers the short-term RMS level towards a target,
+/// reducing gain quickly and raising it slowly, and leaves the gain alone
+/// while the input is near silence so background noise is not pumped up
+pub struct Agc {
+    target: f32,
+    max_gain: f32,
+    floor: f32,
+    power: f32,
+    gain: f32,
+    power_coef: f32,
+    attack: f32,
+    release: f32,
+}
+
+impl Agc {
+    const SILENCE_FLOOR_DB: f32 = -65.0;
+
+    pub fn new(sample_rate: f32, target_db: f32, max_gain_db: f32) -> Self {
+        Self {
+            target: db_to_linear(target_db),
+            max_gain: db_to_linear(max_gain_db),
+            floor: db_to_linear(Self::SILENCE_FLOOR_DB),
+            power: 0.0,
+            gain: 1.0,
+            power_coef: smoothing(sample_rate, 0.3),
+            attack: smoothing(sample_rate, 0.01),
+            release: smoothing(sample_rate, 0.5),
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let x = *sample;
+            self.power = x * x + self.power_coef * (self.power - x * x);
+
+            let rms = self.power.sqrt();
+            if rms > self.floor {
+                let desired = (self.target / rms).min(self.max_gain);
+                let coef = if desired < self.gain { self.attack } else { self.release };
+                self.gain = desired + coef * (self.gain - desired);
+            }
+
+            *sample = x * self.gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: f32 = 16000.0;
+
+    fn tone(freq: f32, amplitude: f32, seconds: f32) -> Vec<f32> {
+        (0..(RATE * seconds) as usize)
+            .map(|i| amplitude * (2.0 * PI * freq * i as f32 / RATE).sin())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    fn db(value: f32) -> f32 {
+        20.0 * value.log10()
+    }
+
+    /// RMS of the second half, after filters and envelopes have settled
+    fn settled_rms(samples: &[f32]) -> f32 {
+        rms(&samples[samples.len() / 2..])
+    }
+
+    #[test]
+    fn high_pass_removes_dc_and_hum_but_keeps_speech_band() {
+        let mut dc = vec![0.3; RATE as usize];
+        HighPass::new(RATE, 80.0).process(&mut dc);
+        assert!(settled_rms(&dc) < 1e-4);
+
+        let mut hum = tone(50.0, 0.5, 2.0);
+        HighPass::new(RATE, 80.0).process(&mut hum);
+        assert!(db(settled_rms(&hum) / settled_rms(&tone(50.0, 0.5, 2.0))) < -12.0);
+
+        let mut voice = tone(1000.0, 0.5, 1.0);
+        HighPass::new(RATE, 80.0).process(&mut voice);
+        let change = db(settled_rms(&voice) / settled_rms(&tone(1000.0, 0.5, 1.0)));
+        assert!(change.abs() < 0.5, "1 kHz changed by {:.2} dB", change);
+    }
+
+    #[test]
+    fn noise_gate_mutes_quiet_input_and_passes_loud_input() {
+        let mut quiet = tone(300.0, db_to_linear(-60.0), 1.0);
+        NoiseGate::new(RATE, -50.0).process(&mut quiet);
+        assert!(settled_rms(&quiet) < 1e-6);
+
+        let mut loud = tone(300.0, db_to_linear(-20.0), 1.0);
+        let reference = settled_rms(&loud);
+        NoiseGate::new(RATE, -50.0).process(&mut loud);
+        assert!((settled_rms(&loud) / reference - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn noise_gate_holds_open_after_speech() {
+        let mut signal = tone(300.0, 0.3, 0.5);
+        signal.extend(tone(300.0, db_to_linear(-60.0), 1.0));
+        NoiseGate::new(RATE, -50.0).process(&mut signal);
+
+        // The quiet tail is passed while the envelope decays and for the
+        // hold time after, then muted
+        let tail_start = (RATE * 0.5) as usize;
+        let held = &signal[tail_start + 160..tail_start + 1600];
+        assert!(rms(held) > db_to_linear(-70.0));
+        assert!(rms(&signal[tail_start + 9600..]) < 1e-6);
+    }
+
+    #[test]
+    fn agc_brings_quiet_and_loud_input_to_target() {
+        for input_db in [-40.0, -6.0] {
+            let mut signal = tone(440.0, db_to_linear(input_db) * 2f32.sqrt(), 4.0);
+            Agc::new(RATE, -20.0, 30.0).process(&mut signal);
+            let output_db = db(settled_rms(&signal));
+            assert!((output_db + 20.0).abs() < 1.5, "{} dB in -> {:.1} dB out", input_db, output_db);
+        }
+    }
+
+    #[test]
+    fn agc_gain_is_capped_and_silence_is_not_boosted() {
+        let mut faint = tone(440.0, db_to_linear(-50.0) * 2f32.sqrt(), 4.0);
+        Agc::new(RATE, -20.0, 12.0).process(&mut faint);
+        assert!((db(settled_rms(&faint)) + 38.0).abs() < 1.0);
+
+        let mut hiss = tone(440.0, db_to_linear(-80.0), 2.0);
+        let reference = settled_rms(&hiss);
+        Agc::new(RATE, -20.0, 30.0).process(&mut hiss);
+        assert!((settled_rms(&hiss) / reference - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn disabled_chain_leaves_samples_untouched() {
+        let config = DspConfig::default();
+        let mut chain = DspChain::new(&config, 16000, 1);
+        let mut samples: Vec<i16> = (0..320).map(|i| (i * 97 % 2000) as i16 - 1000).collect();
+        let original = samples.clone();
+        chain.process(&mut samples);
+        assert_eq!(samples, original);
+        assert_eq!(chain.describe(), "off");
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Why the capture stream is not delivering audio
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureFault {
+    /// No device matched the selection, or it was unplugged
+    DeviceMissing(String),
+    /// Another application holds the device, e.g. in exclusive mode
+    DeviceBusy(String),
+    /// The backend failed to open the stream or reported an error on it
+    StreamError(String),
+    /// The stream is open but no callbacks arrived for this long
+    Stalled(Duration),
+}
+
+impl CaptureFault {
+    /// Classify an error from opening a device or building its stream
+    pub fn from_error(message: &str) -> Self {
+        let lower = message.to_lowercase();
+
+        // WASAPI reports AUDCLNT_E_DEVICE_IN_USE (0x8889000A), ALSA reports EBUSY
+        let busy = ["in use", "busy", "exclusive", "0x8889000a"];
+        let missing = ["no longer available", "no microphone", "no input device"];
+
+        if busy.iter().any(|pattern| lower.contains(pattern)) {
+            CaptureFault::DeviceBusy(message.to_string())
+        } else if missing.iter().any(|pattern| lower.contains(pattern)) {
+            CaptureFault::DeviceMissing(message.to_string())
+        } else {
+            CaptureFault::StreamError(message.to_string())
+        }
+    }
+}
+
+impl fmt::Display for CaptureFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureFault::DeviceMissing(msg) => write!(f, "Microphone unavailable: {}", msg),
+            CaptureFault::DeviceBusy(msg) => write!(f, "Microphone in use by another application: {}", msg),
+            CaptureFault::StreamError(msg) => write!(f, "Capture stream error: {}", msg),
+            CaptureFault::Stalled(duration) => write!(f, "No audio from the microphone for {} ms", duration.as_millis()),
+        }
+    }
+}
+
+/// Exponential backoff between attempts to reopen the microphone
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            next: initial,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.initial;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_open_errors() {
+        assert!(matches!(
+            CaptureFault::from_error("A backend-specific error has occurred: AUDCLNT_E_DEVICE_IN_USE (0x8889000A)"),
+            CaptureFault::DeviceBusy(_)
+        ));
+        assert!(matches!(
+            CaptureFault::from_error("ALSA function 'snd_pcm_open' failed with error 'EBUSY: Device or resource busy'"),
+            CaptureFault::DeviceBusy(_)
+        ));
+        assert!(matches!(
+            CaptureFault::from_error("The requested device is no longer available. For example, it has been unplugged."),
+            CaptureFault::DeviceMissing(_)
+        ));
+        assert!(matches!(
+            CaptureFault::from_error("Unsupported sample format: I24"),
+            CaptureFault::StreamError(_)
+        ));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(3));
+        let delays: Vec<u128> = (0..5).map(|_| backoff.next_delay().as_millis()).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 3000, 3000]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,174 @@
+use super::source::{AudioFrame, AudioSource, FrameFormat};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How far one side may run ahead before the other is padded with silence.
+/// Covers callback jitter; beyond it the other side is stalled, silent
+/// loopback or drifting behind.
+const MAX_SKEW_MS: u32 = 200;
+
+/// Captures two mono sources, "me" and "them", and delivers them aligned as
+/// 2-channel interleaved frames: channel 0 is me, channel 1 is them
+pub struct MeetingSource {
+    me: Box<dyn AudioSource>,
+    them: Box<dyn AudioSource>,
+    format: FrameFormat,
+}
+
+impl MeetingSource {
+    /// `format` is the mono format both sources deliver
+    pub fn new(me: Box<dyn AudioSource>, them: Box<dyn AudioSource>, format: FrameFormat) -> Self {
+        Self { me, them, format }
+    }
+}
+
+impl AudioSource for MeetingSource {
+    fn describe(&self) -> String {
+        format!("meeting: me = {}, them = {}", self.me.describe(), self.them.describe())
+    }
+
+    fn format(&self) -> FrameFormat {
+        FrameFormat {
+            channels: 2,
+            ..self.format
+        }
+    }
+
+    fn start(&mut self, tx: mpsc::UnboundedSender<AudioFrame>) -> Result<(), Box<dyn std::error::Error>> {
+        let (me_tx, mut me_rx) = mpsc::unbounded_channel::<AudioFrame>();
+        let (them_tx, mut them_rx) = mpsc::unbounded_channel::<AudioFrame>();
+        self.me.start(me_tx)?;
+        self.them.start(them_tx)?;
+
+        let format = self.format();
+        let mut aligner = Aligner::new(format);
+        tokio::spawn(async move {
+            let mut me_open = true;
+            let mut them_open = true;
+            while me_open || them_open {
+                tokio::select! {
+                    frame = me_rx.recv(), if me_open => match frame {
+                        Some(frame) => aligner.push(0, &frame.samples),
+                        None => me_open = false,
+                    },
+                    frame = them_rx.recv(), if them_open => match frame {
+                        Some(frame) => aligner.push(1, &frame.samples),
+                        None => them_open = false,
+                    },
+                }
+
+                while let Some(frame) = aligner.pop() {
+                    if tx.send(frame).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Pairs up the audio of two independently clocked sources. Frames go out
+/// once both sides have a full frame. When one side gets more than
+/// `MAX_SKEW_MS` ahead, the other is caught up with silence so the backlog
+/// goes out at once instead of adding latency from then on.
+pub struct Aligner {
+    format: FrameFormat,
+    pending: [VecDeque<i16>; 2],
+    max_skew: usize,
+    // Frames delivered so far, the clock for frame timestamps
+    frames: u64,
+}
+
+impl Aligner {
+    pub fn new(format: FrameFormat) -> Self {
+        Self {
+            format,
+            pending: [VecDeque::new(), VecDeque::new()],
+            max_skew: (format.sample_rate * MAX_SKEW_MS / 1000) as usize,
+            frames: 0,
+        }
+    }
+
+    pub fn push(&mut self, channel: usize, samples: &[i16]) {
+        self.pending[channel].extend(samples.iter().copied());
+    }
+
+    /// The next interleaved frame, if one is due
+    pub fn pop(&mut self) -> Option<AudioFrame> {
+        let frame_size = self.format.frame_size();
+        let ahead = self.pending[0].len().max(self.pending[1].len());
+        if ahead >= frame_size + self.max_skew {
+            for pending in &mut self.pending {
+                pending.resize(ahead, 0);
+            }
+        }
+        if self.pending.iter().any(|pending| pending.len() < frame_size) {
+            return None;
+        }
+
+        let mut samples = Vec::with_capacity(frame_size * 2);
+        for _ in 0..frame_size {
+            for pending in &mut self.pending {
+                samples.extend(pending.pop_front());
+            }
+        }
+
+        let timestamp = Duration::from_millis(self.frames * self.format.frame_ms as u64);
+        self.frames += 1;
+        Some(AudioFrame { timestamp, samples })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: FrameFormat = FrameFormat {
+        sample_rate: 1000,
+        frame_ms: 10,
+        channels: 2,
+    };
+
+    #[test]
+    fn interleaves_once_both_sides_have_a_frame() {
+        let mut aligner = Aligner::new(FORMAT);
+        aligner.push(0, &[1; 15]);
+        assert!(aligner.pop().is_none());
+
+        aligner.push(1, &[2; 15]);
+        let frame = aligner.pop().unwrap();
+        assert_eq!(frame.timestamp, Duration::ZERO);
+        assert_eq!(frame.samples, [1, 2].repeat(10));
+
+        // The leftovers wait for the next frame
+        assert!(aligner.pop().is_none());
+        aligner.push(0, &[3; 5]);
+        aligner.push(1, &[4; 5]);
+        let frame = aligner.pop().unwrap();
+        assert_eq!(frame.timestamp, Duration::from_millis(10));
+        assert_eq!(&frame.samples[..10], &[1, 2].repeat(5)[..]);
+        assert_eq!(&frame.samples[10..], &[3, 4].repeat(5)[..]);
+    }
+
+    #[test]
+    fn catches_up_a_silent_side_once_the_other_runs_ahead() {
+        // Silent loopback delivers nothing; 200 ms of skew is 200 samples
+        let mut aligner = Aligner::new(FORMAT);
+        aligner.push(0, &[7; 209]);
+        assert!(aligner.pop().is_none());
+
+        // The whole backlog goes out, paired with silence
+        aligner.push(0, &[7; 1]);
+        let frames: Vec<AudioFrame> = std::iter::from_fn(|| aligner.pop()).collect();
+        assert_eq!(frames.len(), 21);
+        assert!(frames.iter().all(|f| f.samples == [7, 0].repeat(10)));
+
+        // Audio arriving afterwards lines up again
+        aligner.push(0, &[1; 10]);
+        aligner.push(1, &[2; 10]);
+        assert_eq!(aligner.pop().unwrap().samples, [1, 2].repeat(10));
+    }
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Levels are reported once per window of input
+const WINDOW_MS: u64 = 1000;
+/// Samples at or beyond this magnitude count as clipped
+const CLIP_LEVEL: i32 = i16::MAX as i32 - 1;
+/// Clipping above this share of samples is a problem
+const CLIP_WARN_RATIO: f32 = 0.001;
+/// Consecutive clipped windows before warning
+const CLIP_WARN_WINDOWS: u32 = 3;
+/// Peaks below this mean the input is muted or disconnected
+const SILENT_PEAK_DB: f32 = -70.0;
+/// Consecutive silent windows before warning
+const SILENT_WARN_WINDOWS: u32 = 10;
+/// How fast the noise floor estimate may rise, in dB per second
+const FLOOR_RISE_DB_PER_SEC: f32 = 0.5;
+/// Reported instead of minus infinity for digital silence
+const MIN_DB: f32 = -120.0;
+
+/// Input levels over the last metering window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelSnapshot {
+    pub rms_db: f32,
+    pub peak_db: f32,
+    /// Share of samples at full scale, 0.0-1.0
+    pub clip_ratio: f32,
+    /// Long-run estimate of the background level
+    pub noise_floor_db: f32,
+}
+
+impl fmt::Display for LevelSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RMS {:.1} dBFS, peak {:.1} dBFS, clipping {:.2}%, noise floor {:.1} dBFS",
+            self.rms_db,
+            self.peak_db,
+            self.clip_ratio * 100.0,
+            self.noise_floor_db
+        )
+    }
+}
+
+/// A persistent input problem, or the end of one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LevelAlert {
+    Clipping(f32),
+    NoSignal(Duration),
+    Recovered,
+}
+
+impl fmt::Display for LevelAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelAlert::Clipping(ratio) => write!(
+                f,
+                "Input is clipping ({:.2}% of samples at full scale), lower the microphone gain",
+                ratio * 100.0
+            ),
+            LevelAlert::NoSignal(duration) => write!(
+                f,
+                "No signal from the input for {} s, is the microphone muted?",
+                duration.as_secs()
+            ),
+            LevelAlert::Recovered => write!(f, "Input level back to normal"),
+        }
+    }
+}
+
+/// Measures the capture stream window by window and raises alerts for
+/// persistent clipping or silence. Time is counted in samples, so replayed
+/// input is metered the same as live input. Interleaved channels are
+/// metered together.
+pub struct LevelMeter {
+    window_size: usize,
+    window_seconds: f32,
+    // Accumulators for the current window
+    count: usize,
+    sum_squares: f64,
+    peak: i32,
+    clipped: usize,
+    noise_floor_db: Option<f32>,
+    clipped_windows: u32,
+    silent_windows: u32,
+    alerting: bool,
+    latest: Option<LevelSnapshot>,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let window_size = (sample_rate as u64 * WINDOW_MS / 1000) as usize * channels.max(1) as usize;
+        Self {
+            window_size,
+            window_seconds: WINDOW_MS as f32 / 1000.0,
+            count: 0,
+            sum_squares: 0.0,
+            peak: 0,
+            clipped: 0,
+            noise_floor_db: None,
+            clipped_windows: 0,
+            silent_windows: 0,
+            alerting: false,
+            latest: None,
+        }
+    }
+
+    /// Levels of the last complete window
+    pub fn latest(&self) -> Option<LevelSnapshot> {
+        self.latest
+    }
+
+    /// Meter a frame, returning an alert raised by a window it completed
+    pub fn process(&mut self, samples: &[i16]) -> Option<LevelAlert> {
+        let mut alert = None;
+
+        for &sample in samples {
+            let s = sample as i32;
+            self.sum_squares += (s * s) as f64;
+            self.peak = self.peak.max(s.abs());
+            if s.abs() >= CLIP_LEVEL {
+                self.clipped += 1;
+            }
+            self.count += 1;
+
+            if self.count == self.window_size {
+                alert = self.finish_window().or(alert);
+            }
+        }
+
+        alert
+    }
+
+    fn finish_window(&mut self) -> Option<LevelAlert> {
+        let rms = (self.sum_squares / self.count as f64).sqrt() as f32 / 32768.0;
+        let rms_db = to_db(rms);
+        let peak_db = to_db(self.peak as f32 / 32768.0);
+        let clip_ratio = self.clipped as f32 / self.count as f32;
+
+        // The floor follows quieter windows quickly and louder ones slowly,
+        // so speech barely moves it
+        let floor = match self.noise_floor_db {
+            None => rms_db,
+            Some(floor) if rms_db < floor => floor + (rms_db - floor) * 0.5,
+            Some(floor) => (floor + FLOOR_RISE_DB_PER_SEC * self.window_seconds).min(rms_db),
+        };
+        self.noise_floor_db = Some(floor);
+
+        self.latest = Some(LevelSnapshot {
+            rms_db,
+            peak_db,
+            clip_ratio,
+            noise_floor_db: floor,
+        });
+
+        self.count = 0;
+        self.sum_squares = 0.0;
+        self.peak = 0;
+        self.clipped = 0;
+
+        self.clipped_windows = if clip_ratio > CLIP_WARN_RATIO { self.clipped_windows + 1 } else { 0 };
+        self.silent_windows = if peak_db < SILENT_PEAK_DB { self.silent_windows + 1 } else { 0 };
+
+        // Alert once when a problem becomes persistent, and once when it ends
+        if self.clipped_windows == CLIP_WARN_WINDOWS {
+            self.alerting = true;
+            Some(LevelAlert::Clipping(clip_ratio))
+        } else if self.silent_windows == SILENT_WARN_WINDOWS {
+            self.alerting = true;
+            let seconds = (SILENT_WARN_WINDOWS as u64 * WINDOW_MS) / 1000;
+            Some(LevelAlert::NoSignal(Duration::from_secs(seconds)))
+        } else if self.alerting && self.clipped_windows == 0 && self.silent_windows == 0 {
+            self.alerting = false;
+            Some(LevelAlert::Recovered)
+        } else {
+            None
+        }
+    }
+}
+
+fn to_db(linear: f32) -> f32 {
+    if linear > 0.0 {
+        (20.0 * linear.log10()).max(MIN_DB)
+    } else {
+        MIN_DB
+    }
+}
+
+/// Latest levels shared with the tray and diagnostics
+#[derive(Default)]
+pub struct LevelMonitor {
+    latest: Mutex<Option<LevelSnapshot>>,
+}
+
+impl LevelMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn publish(&self, levels: LevelSnapshot) {
+        *self.latest.lock().unwrap() = Some(levels);
+    }
+
+    /// Read by the tray tooltip, which only exists on Windows
+    #[cfg(windows)]
+    pub fn latest(&self) -> Option<LevelSnapshot> {
+        *self.latest.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn tone(amplitude: f32, seconds: f32) -> Vec<i16> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| {
+                let x = amplitude * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / RATE as f32).sin();
+                (x * 32767.0).round().clamp(-32768.0, 32767.0) as i16
+            })
+            .collect()
+    }
+
+    /// Feed 100 ms frames, collecting alerts
+    fn run(meter: &mut LevelMeter, samples: &[i16]) -> Vec<LevelAlert> {
+        samples
+            .chunks(RATE as usize / 10)
+            .filter_map(|frame| meter.process(frame))
+            .collect()
+    }
+
+    #[test]
+    fn measures_rms_and_peak_of_a_tone() {
+        let mut meter = LevelMeter::new(RATE, 1);
+        run(&mut meter, &tone(0.5, 1.0));
+
+        let levels = meter.latest().unwrap();
+        assert!((levels.peak_db - -6.02).abs() < 0.1);
+        assert!((levels.rms_db - -9.03).abs() < 0.1);
+        assert_eq!(levels.clip_ratio, 0.0);
+    }
+
+    #[test]
+    fn a_stereo_window_spans_the_same_time() {
+        let mut meter = LevelMeter::new(RATE, 2);
+        let stereo: Vec<i16> = tone(0.5, 1.0).iter().flat_map(|&s| [s, 0]).collect();
+        run(&mut meter, &stereo[..RATE as usize]);
+        assert_eq!(meter.latest(), None);
+
+        run(&mut meter, &stereo[RATE as usize..]);
+        // Half the samples are silent, so the power halves
+        assert!((meter.latest().unwrap().rms_db - -12.04).abs() < 0.1);
+    }
+
+    #[test]
+    fn warns_about_persistent_clipping_then_recovery() {
+        let mut meter = LevelMeter::new(RATE, 1);
+        let mut alerts = run(&mut meter, &tone(2.0, 4.0));
+        assert!(matches!(alerts.as_slice(), [LevelAlert::Clipping(ratio)] if *ratio > 0.1));
+
+        alerts = run(&mut meter, &tone(0.3, 2.0));
+        assert_eq!(alerts, vec![LevelAlert::Recovered]);
+    }
+
+    #[test]
+    fn warns_about_a_silent_input_once() {
+        let mut meter = LevelMeter::new(RATE, 1);
+        let alerts = run(&mut meter, &vec![0; RATE as usize * 30]);
+        assert_eq!(alerts, vec![LevelAlert::NoSignal(Duration::from_secs(10))]);
+        assert_eq!(meter.latest().unwrap().peak_db, MIN_DB);
+    }
+
+    #[test]
+    fn noise_floor_ignores_short_speech() {
+        let mut meter = LevelMeter::new(RATE, 1);
+        let mut signal = tone(0.001, 10.0);
+        signal.extend(tone(0.3, 2.0));
+        run(&mut meter, &signal);
+
+        // -63 dBFS background, a 2 s utterance lifts the estimate by ~1 dB
+        let floor = meter.latest().unwrap().noise_floor_db;
+        assert!((floor - -63.0).abs() < 1.5, "noise floor {:.1}", floor);
+    }
+}
@@ -1,3 +1,20 @@
 pub mod capture;
+pub mod device;
+pub mod downmix;
+pub mod dsp;
+pub mod health;
+pub mod meeting;
+pub mod meter;
+#[cfg(feature = "opus")]
+pub mod opus;
+pub mod pause;
+pub mod preroll;
+pub mod recorder;
+pub mod remote;
+pub mod resample;
+pub mod source;
+pub mod stdin;
+pub mod uplink;
 pub mod vad;
 pub mod ducking;
+pub mod wav;
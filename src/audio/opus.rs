@@ -0,0 +1,281 @@
+use audiopus::coder::Encoder;
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use std::convert::TryFrom;
+
+/// Opus frame duration, the usual choice for speech
+const OPUS_FRAME_MS: u32 = 20;
+/// Largest packet the encoder may produce, as recommended by libopus
+const MAX_PACKET_BYTES: usize = 4000;
+/// Ogg/Opus granule positions always count 48 kHz samples
+const GRANULE_RATE: u64 = 48000;
+
+/// Encodes mono PCM into 20 ms Opus packets, buffering partial frames
+pub struct OpusEncoder {
+    encoder: Encoder,
+    sample_rate: u32,
+    frame_size: usize,
+    pending: Vec<i16>,
+    scratch: Vec<u8>,
+}
+
+impl OpusEncoder {
+    /// `sample_rate` must be one Opus supports: 8, 12, 16, 24 or 48 kHz
+    pub fn new(sample_rate: u32, bitrate: u32) -> Result<Self, audiopus::Error> {
+        let rate = SampleRate::try_from(sample_rate as i32)?;
+        let mut encoder = Encoder::new(rate, Channels::Mono, Application::Voip)?;
+        encoder.set_bitrate(Bitrate::BitsPerSecond(bitrate as i32))?;
+
+        let frame_size = (sample_rate * OPUS_FRAME_MS / 1000) as usize;
+        Ok(Self {
+            encoder,
+            sample_rate,
+            frame_size,
+            pending: Vec::with_capacity(frame_size * 2),
+            scratch: vec![0; MAX_PACKET_BYTES],
+        })
+    }
+
+    /// Samples of algorithmic delay a decoder skips at the start
+    pub fn lookahead(&self) -> Result<u32, audiopus::Error> {
+        self.encoder.lookahead()
+    }
+
+    /// Encode all whole 20 ms frames, keeping the remainder for the next call
+    pub fn encode(&mut self, samples: &[i16]) -> Result<Vec<Vec<u8>>, audiopus::Error> {
+        self.pending.extend_from_slice(samples);
+
+        let mut packets = Vec::new();
+        let mut used = 0;
+        while self.pending.len() - used >= self.frame_size {
+            let len = self
+                .encoder
+                .encode(&self.pending[used..used + self.frame_size], &mut self.scratch)?;
+            packets.push(self.scratch[..len].to_vec());
+            used += self.frame_size;
+        }
+        self.pending.drain(..used);
+
+        Ok(packets)
+    }
+
+    /// Encode what is left, padded to a whole frame with silence
+    pub fn flush(&mut self) -> Result<Option<Vec<u8>>, audiopus::Error> {
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+        self.pending.resize(self.frame_size, 0);
+        let packet = self.encode(&[])?.pop();
+        Ok(packet)
+    }
+}
+
+/// Wraps Opus packets in an Ogg stream (RFC 7845). Each call ends a page,
+/// so audio is never held back waiting for a page to fill.
+pub struct OggOpusEncoder {
+    opus: OpusEncoder,
+    writer: PacketWriter<Vec<u8>>,
+    serial: u32,
+    pre_skip: u64,
+    // Granule position after the last packet, and input samples so far
+    granule: u64,
+    input_samples: u64,
+}
+
+impl OggOpusEncoder {
+    pub fn new(sample_rate: u32, bitrate: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let opus = OpusEncoder::new(sample_rate, bitrate)?;
+        let pre_skip = opus.lookahead()? as u64 * GRANULE_RATE / sample_rate as u64;
+        let serial = std::process::id();
+
+        let mut writer = PacketWriter::new(Vec::new());
+        writer.write_packet(
+            opus_head(pre_skip as u16, sample_rate).into_boxed_slice(),
+            serial,
+            PacketWriteEndInfo::EndPage,
+            0,
+        )?;
+        writer.write_packet(opus_tags().into_boxed_slice(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(Self {
+            opus,
+            writer,
+            serial,
+            pre_skip,
+            granule: 0,
+            input_samples: 0,
+        })
+    }
+
+    /// Encode samples, returning the bytes of any completed pages. The
+    /// first call also returns the two header pages.
+    pub fn encode(&mut self, samples: &[i16]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.input_samples += samples.len() as u64;
+        let packets = self.opus.encode(samples)?;
+
+        let count = packets.len();
+        for (i, packet) in packets.into_iter().enumerate() {
+            let end = if i + 1 == count {
+                PacketWriteEndInfo::EndPage
+            } else {
+                PacketWriteEndInfo::NormalPacket
+            };
+            self.write(packet, end)?;
+        }
+
+        Ok(std::mem::take(self.writer.inner_mut()))
+    }
+
+    /// Encode the remaining audio and end the stream
+    pub fn finish(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        // The last page always carries a packet, even one of pure silence
+        let packet = match self.opus.flush()? {
+            Some(packet) => packet,
+            None => self.opus.encode(&vec![0; self.opus.frame_size])?.remove(0),
+        };
+        self.write(packet, PacketWriteEndInfo::EndStream)?;
+        Ok(std::mem::take(self.writer.inner_mut()))
+    }
+
+    fn write(&mut self, packet: Vec<u8>, end: PacketWriteEndInfo) -> std::io::Result<()> {
+        self.granule += self.opus.frame_size as u64 * GRANULE_RATE / self.opus.sample_rate as u64;
+
+        // The final granule marks where the real audio ends, trimming padding
+        let granule = if end == PacketWriteEndInfo::EndStream {
+            self.pre_skip + self.input_samples * GRANULE_RATE / self.opus.sample_rate as u64
+        } else {
+            self.granule
+        };
+        self.writer.write_packet(packet.into_boxed_slice(), self.serial, end, granule)
+    }
+}
+
+/// Identification header, RFC 7845 section 5.1
+fn opus_head(pre_skip: u16, input_sample_rate: u32) -> Vec<u8> {
+    let mut head = Vec::with_capacity(19);
+    head.extend_from_slice(b"OpusHead");
+    head.push(1); // version
+    head.push(1); // channels
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&input_sample_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // mapping family: mono/stereo
+    head
+}
+
+/// Comment header, RFC 7845 section 5.2
+fn opus_tags() -> Vec<u8> {
+    let vendor = concat!("dictation-rs ", env!("CARGO_PKG_VERSION"));
+    let mut tags = Vec::new();
+    tags.extend_from_slice(b"OpusTags");
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor.as_bytes());
+    tags.extend_from_slice(&0u32.to_le_bytes()); // no user comments
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audiopus::coder::Decoder;
+    use audiopus::packet::Packet;
+    use audiopus::MutSignals;
+    use ogg::reading::PacketReader;
+    use std::io::Cursor;
+
+    const RATE: u32 = 16000;
+
+    fn tone(freq: f32, seconds: f32) -> Vec<i16> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| (8000.0 * (2.0 * std::f32::consts::PI * freq * i as f32 / RATE as f32).sin()) as i16)
+            .collect()
+    }
+
+    fn decode(packets: &[Vec<u8>]) -> Vec<i16> {
+        let mut decoder = Decoder::new(SampleRate::Hz16000, Channels::Mono).unwrap();
+        let mut output = Vec::new();
+        let mut frame = vec![0i16; 5760];
+        for packet in packets {
+            let packet = Packet::try_from(packet).unwrap();
+            let signals = MutSignals::try_from(&mut frame).unwrap();
+            let decoded = decoder.decode(Some(packet), signals, false).unwrap();
+            output.extend_from_slice(&frame[..decoded]);
+        }
+        output
+    }
+
+    fn rms(samples: &[i16]) -> f64 {
+        (samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    /// Zero crossings per second / 2
+    fn frequency(samples: &[i16]) -> f64 {
+        let crossings = samples.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+        crossings as f64 / 2.0 / (samples.len() as f64 / RATE as f64)
+    }
+
+    fn assert_matches(original: &[i16], decoded: &[i16]) {
+        // Skip the encoder's warm-up, compare what both cover
+        let len = original.len().min(decoded.len());
+        let (a, b) = (&original[RATE as usize / 4..len], &decoded[RATE as usize / 4..len]);
+        let level_db = 20.0 * (rms(b) / rms(a)).log10();
+        assert!(level_db.abs() < 1.0, "level changed by {:.2} dB", level_db);
+        assert!((frequency(b) - 440.0).abs() < 5.0, "decoded {:.1} Hz", frequency(b));
+    }
+
+    #[test]
+    fn raw_packets_round_trip() {
+        let input = tone(440.0, 1.0);
+        let mut encoder = OpusEncoder::new(RATE, 32000).unwrap();
+        let lookahead = encoder.lookahead().unwrap() as usize;
+
+        // 100 ms pipeline frames in, 20 ms packets out
+        let mut packets = Vec::new();
+        for frame in input.chunks(1600) {
+            packets.extend(encoder.encode(frame).unwrap());
+        }
+        assert_eq!(packets.len(), 50);
+        assert!(encoder.flush().unwrap().is_none());
+
+        // Far smaller than the 32000 bytes of linear16
+        let bytes: usize = packets.iter().map(Vec::len).sum();
+        assert!(bytes < 5000, "{} bytes", bytes);
+
+        let decoded = decode(&packets);
+        assert_matches(&input, &decoded[lookahead..]);
+    }
+
+    #[test]
+    fn ogg_stream_round_trip() {
+        let input = tone(440.0, 1.05);
+        let mut encoder = OggOpusEncoder::new(RATE, 32000).unwrap();
+        let mut stream = Vec::new();
+        for frame in input.chunks(1600) {
+            stream.extend(encoder.encode(frame).unwrap());
+        }
+        stream.extend(encoder.finish().unwrap());
+
+        let mut reader = PacketReader::new(Cursor::new(stream));
+        let head = reader.read_packet_expected().unwrap();
+        assert_eq!(&head.data[..8], b"OpusHead");
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+        let tags = reader.read_packet_expected().unwrap();
+        assert_eq!(&tags.data[..8], b"OpusTags");
+
+        let mut packets = Vec::new();
+        let mut last = None;
+        while let Some(packet) = reader.read_packet().unwrap() {
+            last = Some((packet.last_in_stream(), packet.absgp_page()));
+            packets.push(packet.data);
+        }
+
+        // 1.05 s needs 53 packets, the last one padded; the final granule
+        // trims the padding again
+        assert_eq!(packets.len(), 53);
+        assert_eq!(last, Some((true, pre_skip + 50400)));
+
+        let decoded = decode(&packets);
+        let skip = (pre_skip / 3) as usize;
+        assert_matches(&input, &decoded[skip..]);
+    }
+}
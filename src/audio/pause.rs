@@ -0,0 +1,149 @@
+use super::preroll::PreRollBuffer;
+use std::time::Duration;
+
+/// Cuts long pauses out of the audio of an open utterance, since Deepgram
+/// bills for silence too.
+///
+/// The first `keep_ms` of each pause is passed on, so Deepgram still hears
+/// the gap it endpoints on; the rest is held back, and only its last
+/// `resume_ms` is sent when speech resumes, ahead of the speech, so a soft
+/// onset is not lost.
+pub struct PauseTrimmer {
+    keep: u64,
+    sample_rate: u32,
+    channels: u16,
+    // Samples per channel of the current pause, and of those dropped since
+    // the last `take_skipped`
+    pause: u64,
+    skipped: u64,
+    tail: PreRollBuffer,
+}
+
+impl PauseTrimmer {
+    /// A `keep_ms` of 0 passes every pause in full
+    pub fn new(sample_rate: u32, channels: u16, keep_ms: u64, resume_ms: u64) -> Self {
+        Self {
+            keep: sample_rate as u64 * keep_ms / 1000,
+            sample_rate,
+            channels,
+            pause: 0,
+            skipped: 0,
+            tail: PreRollBuffer::new(sample_rate, channels, resume_ms),
+        }
+    }
+
+    /// The audio to send for this frame, if any. `speech` is whether the
+    /// VAD heard speech in it.
+    pub fn admit(&mut self, frame: Vec<i16>, speech: bool) -> Option<Vec<i16>> {
+        let frames = (frame.len() / self.channels.max(1) as usize) as u64;
+        if speech {
+            self.pause = 0;
+            let mut audio = self.tail.take();
+            if audio.is_empty() {
+                return Some(frame);
+            }
+            self.skipped -= (audio.len() / self.channels.max(1) as usize) as u64;
+            audio.extend(frame);
+            return Some(audio);
+        }
+
+        self.pause += frames;
+        if self.keep == 0 || self.pause <= self.keep {
+            return Some(frame);
+        }
+        self.skipped += frames;
+        self.tail.push(&frame);
+        None
+    }
+
+    /// Silence held back since the last call, which starts a new utterance
+    pub fn take_skipped(&mut self) -> Duration {
+        let skipped = Duration::from_secs_f64(self.skipped as f64 / self.sample_rate as f64);
+        self.pause = 0;
+        self.skipped = 0;
+        self.tail.clear();
+        skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_start_of_a_pause_and_the_audio_before_speech() {
+        // 1 kHz, 100-sample frames: keep 300 ms of each pause, resume with 200 ms
+        let mut trimmer = PauseTrimmer::new(1000, 1, 300, 200);
+        assert!(trimmer.admit(vec![1; 100], true).is_some());
+        for _ in 0..3 {
+            assert!(trimmer.admit(vec![0; 100], false).is_some());
+        }
+        for i in 0..7 {
+            assert_eq!(trimmer.admit(vec![i; 100], false), None);
+        }
+
+        let resumed = trimmer.admit(vec![9; 100], true).unwrap();
+        assert_eq!(resumed.len(), 300);
+        assert_eq!(&resumed[..100], &[5; 100]);
+        assert_eq!(&resumed[200..], &[9; 100]);
+        assert_eq!(trimmer.take_skipped(), Duration::from_millis(500));
+        assert_eq!(trimmer.take_skipped(), Duration::ZERO);
+    }
+
+    #[test]
+    fn zero_keep_passes_every_pause() {
+        let mut trimmer = PauseTrimmer::new(1000, 1, 0, 200);
+        assert!(trimmer.admit(vec![1; 100], true).is_some());
+        for _ in 0..50 {
+            assert_eq!(trimmer.admit(vec![0; 100], false), Some(vec![0; 100]));
+        }
+        assert_eq!(trimmer.admit(vec![1; 100], true), Some(vec![1; 100]));
+        assert_eq!(trimmer.take_skipped(), Duration::ZERO);
+    }
+
+    #[test]
+    fn counts_interleaved_frames_per_channel() {
+        // Two channels: 200 samples are 100 ms at 1 kHz
+        let mut trimmer = PauseTrimmer::new(1000, 2, 300, 100);
+        assert!(trimmer.admit(vec![1; 200], true).is_some());
+        for _ in 0..3 {
+            assert!(trimmer.admit(vec![0; 200], false).is_some());
+        }
+        for _ in 0..4 {
+            assert_eq!(trimmer.admit(vec![0; 200], false), None);
+        }
+
+        // The resume tail holds 100 ms of both channels
+        assert_eq!(trimmer.admit(vec![1; 200], true).unwrap().len(), 400);
+        assert_eq!(trimmer.take_skipped(), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn a_short_drop_resumes_with_all_of_it() {
+        // Only 100 ms is held back, less than the 500 ms resume tail
+        let mut trimmer = PauseTrimmer::new(1000, 1, 300, 500);
+        assert!(trimmer.admit(vec![1; 100], true).is_some());
+        for _ in 0..3 {
+            assert!(trimmer.admit(vec![0; 100], false).is_some());
+        }
+        assert_eq!(trimmer.admit(vec![7; 100], false), None);
+
+        let resumed = trimmer.admit(vec![9; 100], true).unwrap();
+        assert_eq!(resumed.len(), 200);
+        assert_eq!(&resumed[..100], &[7; 100]);
+        assert_eq!(trimmer.take_skipped(), Duration::ZERO);
+    }
+
+    #[test]
+    fn skipped_time_is_counted_per_utterance() {
+        let mut trimmer = PauseTrimmer::new(1000, 1, 200, 0);
+        for pause in [5, 3] {
+            assert!(trimmer.admit(vec![1; 100], true).is_some());
+            for _ in 0..pause {
+                trimmer.admit(vec![0; 100], false);
+            }
+            // An utterance ending in a pause does not carry it into the next
+            assert_eq!(trimmer.take_skipped(), Duration::from_millis((pause - 2) * 100));
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+
+/// Fixed-length history of the most recent audio.
+///
+/// Kept while no Deepgram stream is open so the onset of an utterance,
+/// which arrives before the VAD triggers, can be sent once it does.
+pub struct PreRollBuffer {
+    samples: VecDeque<i16>,
+    capacity: usize,
+}
+
+impl PreRollBuffer {
+    /// Holds whole interleaved frames of `channels` samples each
+    pub fn new(sample_rate: u32, channels: u16, duration_ms: u64) -> Self {
+        let capacity = (sample_rate as u64 * duration_ms / 1000) as usize * channels as usize;
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[i16]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        // Only the tail of an oversized chunk can survive
+        let chunk = &chunk[chunk.len().saturating_sub(self.capacity)..];
+        let overflow = (self.samples.len() + chunk.len()).saturating_sub(self.capacity);
+        self.samples.drain(..overflow);
+        self.samples.extend(chunk.iter().copied());
+    }
+
+    /// Take the buffered audio, oldest first, leaving the buffer empty
+    pub fn take(&mut self) -> Vec<i16> {
+        self.samples.drain(..).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_most_recent_audio() {
+        // 10 ms at 1 kHz = 10 samples
+        let mut preroll = PreRollBuffer::new(1000, 1, 10);
+        preroll.push(&[1, 2, 3, 4, 5, 6]);
+        preroll.push(&[7, 8, 9, 10, 11, 12]);
+        assert_eq!(preroll.take(), vec![3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        assert!(preroll.take().is_empty());
+
+        preroll.push(&(0..25).collect::<Vec<i16>>());
+        assert_eq!(preroll.take(), (15..25).collect::<Vec<i16>>());
+    }
+
+    #[test]
+    fn zero_duration_buffers_nothing() {
+        let mut preroll = PreRollBuffer::new(16000, 1, 0);
+        preroll.push(&[1, 2, 3]);
+        assert!(preroll.take().is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+use crate::config::{Config, RecorderConfig};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const FILE_PREFIX: &str = "utterance-";
+
+/// Saves each utterance sent to Deepgram as a WAV file with a JSON sidecar
+/// holding the transcript, timings and settings, for auditing misrecognitions
+#[derive(Clone)]
+pub struct Recorder {
+    dir: PathBuf,
+    sample_rate: u32,
+    channels: u16,
+    max_total_bytes: u64,
+    max_age: Duration,
+}
+
+impl Recorder {
+    /// `None` unless recording is enabled in the config
+    pub fn from_config(
+        config: &RecorderConfig,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let dir = match &config.dir {
+            Some(dir) => dir.clone(),
+            None => Self::default_dir()?,
+        };
+        fs::create_dir_all(&dir)?;
+        println!("[Recorder] Saving utterances to {}", dir.display());
+
+        Ok(Some(Self {
+            dir,
+            sample_rate,
+            channels,
+            max_total_bytes: config.max_total_mb * 1024 * 1024,
+            max_age: Duration::from_secs(config.max_age_days * 24 * 60 * 60),
+        }))
+    }
+
+    pub fn default_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        Ok(directories::BaseDirs::new()
+            .ok_or("Failed to get base directories")?
+            .data_local_dir()
+            .join("dictation")
+            .join("recordings"))
+    }
+
+    /// Open a recording starting at `start`, the stream time of its first
+    /// sample. Transcripts sent to the returned sender are passed on to
+    /// `text_tx` and kept for the sidecar.
+    pub fn start(
+        &self,
+        config: &Config,
+        start: Duration,
+        speech_start: Duration,
+        text_tx: mpsc::UnboundedSender<String>,
+    ) -> Result<(Recording, mpsc::UnboundedSender<String>), Box<dyn std::error::Error>> {
+        let started_at_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let stem = format!("{}{}", FILE_PREFIX, started_at_ms);
+        let spec = WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let writer = WavWriter::create(self.dir.join(format!("{}.wav", stem)), spec)?;
+
+        let (tee_tx, mut tee_rx) = mpsc::unbounded_channel::<String>();
+        let transcript = tokio::spawn(async move {
+            let mut segments = Vec::new();
+            while let Some(text) = tee_rx.recv().await {
+                let _ = text_tx.send(text.clone());
+                segments.push(text);
+            }
+            segments
+        });
+
+        let recording = Recording {
+            writer,
+            stem,
+            started_at_ms,
+            start,
+            speech_start,
+            samples: 0,
+            settings: settings_json(config),
+            transcript,
+        };
+        Ok((recording, tee_tx))
+    }
+
+    /// Close the WAV file, then write the sidecar once the transcript is
+    /// complete and rotate old recordings
+    pub fn finish(&self, recording: Recording, reason: &'static str) -> JoinHandle<()> {
+        let recorder = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = recorder.save(recording, reason).await {
+                eprintln!("[Recorder] Failed to save recording: {}", e);
+            }
+        })
+    }
+
+    async fn save(&self, recording: Recording, reason: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Recording {
+            writer,
+            stem,
+            started_at_ms,
+            start,
+            speech_start,
+            samples,
+            settings,
+            transcript,
+        } = recording;
+        writer.finalize()?;
+
+        // Ends when the Deepgram client drops its sender
+        let segments = transcript.await.unwrap_or_default();
+
+        let duration = samples as f64 / self.channels as f64 / self.sample_rate as f64;
+        let sidecar = json!({
+            "audio": format!("{}.wav", stem),
+            "started_at_unix_ms": started_at_ms as u64,
+            "stream_start_s": start.as_secs_f64(),
+            "speech_start_s": speech_start.as_secs_f64(),
+            "preroll_s": speech_start.saturating_sub(start).as_secs_f64(),
+            "duration_s": duration,
+            "channels": self.channels,
+            "ended_by": reason,
+            "transcript": segments.join(" "),
+            "segments": segments,
+            "config": settings,
+        });
+        fs::write(
+            self.dir.join(format!("{}.json", stem)),
+            serde_json::to_string_pretty(&sidecar)?,
+        )?;
+        println!("[Recorder] Saved {} ({:.1}s)", stem, duration);
+
+        self.rotate()
+    }
+
+    fn rotate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let entries = list_recordings(&self.dir)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+        for stem in expired(entries, now, self.max_age, self.max_total_bytes) {
+            for extension in ["wav", "json"] {
+                let _ = fs::remove_file(self.dir.join(format!("{}.{}", stem, extension)));
+            }
+            println!("[Recorder] Removed old recording {}", stem);
+        }
+        Ok(())
+    }
+}
+
+/// An utterance being written
+pub struct Recording {
+    writer: WavWriter<BufWriter<File>>,
+    stem: String,
+    started_at_ms: u128,
+    start: Duration,
+    speech_start: Duration,
+    samples: u64,
+    settings: serde_json::Value,
+    transcript: JoinHandle<Vec<String>>,
+}
+
+impl Recording {
+    pub fn write(&mut self, samples: &[i16]) {
+        for &sample in samples {
+            if let Err(e) = self.writer.write_sample(sample) {
+                eprintln!("[Recorder] Write error: {}", e);
+                return;
+            }
+        }
+        self.samples += samples.len() as u64;
+    }
+}
+
+/// The settings that affect recognition, stored with every recording
+fn settings_json(config: &Config) -> serde_json::Value {
+    json!({
+        "model": config.deepgram.model,
+        "language": config.deepgram.language,
+        "sample_rate": config.audio.sample_rate,
+        "frame_ms": config.audio.frame_ms,
+        "preroll_ms": config.audio.preroll_ms,
+        "channel_mix": config.audio.channel_mix,
+        "silence_threshold_ms": config.audio.silence_threshold_ms,
+        "vad": config.vad,
+        "dsp": config.dsp,
+    })
+}
+
+/// A saved utterance: its file stem, combined size and recording time
+struct Entry {
+    stem: String,
+    bytes: u64,
+    started_at: Duration,
+}
+
+fn list_recordings(dir: &Path) -> std::io::Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = Vec::new();
+
+    for file in fs::read_dir(dir)? {
+        let file = file?;
+        let name = file.file_name().to_string_lossy().into_owned();
+        let Some((stem, _)) = name.rsplit_once('.') else {
+            continue;
+        };
+        let Some(started_at_ms) = stem.strip_prefix(FILE_PREFIX).and_then(|ms| ms.parse().ok()) else {
+            continue;
+        };
+
+        let bytes = file.metadata()?.len();
+        match entries.iter_mut().find(|e| e.stem == stem) {
+            Some(entry) => entry.bytes += bytes,
+            None => entries.push(Entry {
+                stem: stem.to_string(),
+                bytes,
+                started_at: Duration::from_millis(started_at_ms),
+            }),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Recordings to delete: everything older than `max_age`, then the oldest
+/// until the rest fit in `max_total_bytes`
+fn expired(mut entries: Vec<Entry>, now: Duration, max_age: Duration, max_total_bytes: u64) -> Vec<String> {
+    entries.sort_by_key(|e| e.started_at);
+
+    let mut total: u64 = entries.iter().map(|e| e.bytes).sum();
+    let mut remove = Vec::new();
+    for entry in entries {
+        let too_old = now.saturating_sub(entry.started_at) > max_age;
+        if too_old || total > max_total_bytes {
+            total -= entry.bytes;
+            remove.push(entry.stem);
+        }
+    }
+    remove
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(started_at_s: u64, bytes: u64) -> Entry {
+        Entry {
+            stem: format!("{}{}", FILE_PREFIX, started_at_s * 1000),
+            bytes,
+            started_at: Duration::from_secs(started_at_s),
+        }
+    }
+
+    #[test]
+    fn expires_by_age_then_by_total_size_oldest_first() {
+        let day = 24 * 60 * 60;
+        let now = Duration::from_secs(40 * day);
+        let entries = vec![
+            entry(35 * day, 300),
+            entry(5 * day, 100),
+            entry(39 * day, 400),
+            entry(20 * day, 200),
+        ];
+
+        // The day-5 recording is past 30 days old; the rest total 900 bytes,
+        // so the next oldest goes too to get under 800
+        let removed = expired(entries, now, Duration::from_secs(30 * day), 800);
+        assert_eq!(
+            removed,
+            vec![
+                format!("{}{}", FILE_PREFIX, 5 * day * 1000),
+                format!("{}{}", FILE_PREFIX, 20 * day * 1000),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_everything_within_limits() {
+        let entries = vec![entry(10, 100), entry(20, 100)];
+        assert!(expired(entries, Duration::from_secs(30), Duration::from_secs(60), 1000).is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+use super::source::{AudioFrame, AudioSource, FrameFormat, FrameSender};
+use crate::config::RemoteConfig;
+use futures_util::StreamExt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Capture page served to the phone or second machine
+const PAGE: &str = include_str!("remote.html");
+/// Largest request head accepted before the connection is dropped
+const MAX_HEAD_BYTES: usize = 8192;
+/// Time a client gets to send its request head
+const HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accepts audio from a browser or phone over a local WebSocket.
+///
+/// Serves a capture page at `/?token=...`; the page streams mono s16le PCM
+/// to `/ws?token=...&rate=...`. One client at a time; while none is
+/// connected the source delivers silence, so an utterance cut off by a
+/// dropped connection still ends.
+pub struct RemoteSource {
+    bind: SocketAddr,
+    token: String,
+    format: FrameFormat,
+}
+
+impl RemoteSource {
+    pub fn new(config: &RemoteConfig, format: FrameFormat) -> Result<Self, Box<dyn std::error::Error>> {
+        let bind = config
+            .bind
+            .ok_or("remote.bind must be set for the remote source, e.g. \"127.0.0.1:8765\"")?;
+        check_bind_address(bind.ip())?;
+
+        // Without a configured token, pair with a fresh one every run
+        let token = match &config.token {
+            Some(token) if token.len() < 16 => return Err("remote.token must be at least 16 characters".into()),
+            Some(token) => token.clone(),
+            None => rand::random::<[u8; 16]>().iter().map(|b| format!("{:02x}", b)).collect(),
+        };
+
+        Ok(Self { bind, token, format })
+    }
+}
+
+impl AudioSource for RemoteSource {
+    fn describe(&self) -> String {
+        format!("remote microphone on http://{}", self.bind)
+    }
+
+    fn format(&self) -> FrameFormat {
+        self.format
+    }
+
+    fn start(&mut self, tx: mpsc::UnboundedSender<AudioFrame>) -> Result<(), Box<dyn std::error::Error>> {
+        // Bind now so a taken port fails at startup rather than silently
+        let listener = std::net::TcpListener::bind(self.bind)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        println!("[Remote] Open http://{}/?token={} on the capturing device", self.bind, self.token);
+
+        let server = Server {
+            token: self.token.clone(),
+            format: self.format,
+            tx,
+            busy: AtomicBool::new(false),
+            position: Mutex::new(0),
+        };
+        let server = Arc::new(server);
+        tokio::spawn(fill_silence(server.clone()));
+        tokio::spawn(serve(listener, server));
+        Ok(())
+    }
+}
+
+/// Refuse wildcard and public addresses: the listener is for the local
+/// machine or a trusted LAN, chosen explicitly
+fn check_bind_address(ip: IpAddr) -> Result<(), String> {
+    let local = match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        // Loopback, unique local (fc00::/7) or link-local (fe80::/10)
+        IpAddr::V6(ip) => {
+            ip.is_loopback() || (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80
+        }
+    };
+    if local {
+        Ok(())
+    } else {
+        Err(format!(
+            "remote.bind must be a loopback or LAN address, not {}; bind to the interface the phone can reach",
+            ip
+        ))
+    }
+}
+
+struct Server {
+    token: String,
+    format: FrameFormat,
+    tx: mpsc::UnboundedSender<AudioFrame>,
+    // Set while a client is streaming
+    busy: AtomicBool,
+    // Samples delivered so far, so timestamps continue across clients and
+    // the silence between them
+    position: Mutex<u64>,
+}
+
+/// Deliver silent frames in real time while no client is streaming
+async fn fill_silence(server: Arc<Server>) {
+    let frame_size = server.format.frame_size();
+    let mut ticker = tokio::time::interval(Duration::from_millis(server.format.frame_ms as u64));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    while !server.tx.is_closed() {
+        ticker.tick().await;
+        // A client takes over under the lock, so its frames follow on
+        let mut position = server.position.lock().unwrap();
+        if server.busy.load(Ordering::SeqCst) {
+            continue;
+        }
+        let timestamp = Duration::from_secs_f64(*position as f64 / server.format.sample_rate as f64);
+        let _ = server.tx.send(AudioFrame {
+            timestamp,
+            samples: vec![0; frame_size],
+        });
+        *position += frame_size as u64;
+    }
+}
+
+async fn serve(listener: TcpListener, server: Arc<Server>) {
+    while !server.tx.is_closed() {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("[Remote] Accept error: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, peer, &server).await {
+                eprintln!("[Remote] {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle(stream: TcpStream, peer: SocketAddr, server: &Server) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(head) = tokio::time::timeout(HEAD_TIMEOUT, peek_head(&stream)).await?? else {
+        return Ok(());
+    };
+
+    match route(&head, &server.token) {
+        Route::Audio { rate } => {
+            if server.busy.swap(true, Ordering::SeqCst) {
+                return respond(stream, head.len(), "409 Conflict", "text/plain", "Another client is streaming").await;
+            }
+            let result = stream_audio(stream, peer, rate, server).await;
+            server.busy.store(false, Ordering::SeqCst);
+            result
+        }
+        Route::Page => respond(stream, head.len(), "200 OK", "text/html; charset=utf-8", PAGE).await,
+        Route::Reject(status) => {
+            if status.starts_with("403") {
+                eprintln!("[Remote] Rejected {}: wrong or missing token", peer);
+            }
+            respond(stream, head.len(), status, "text/plain", status).await
+        }
+    }
+}
+
+/// Read the request head without consuming it, so the WebSocket handshake
+/// can still read the whole request
+async fn peek_head(stream: &TcpStream) -> std::io::Result<Option<String>> {
+    let mut buf = vec![0u8; MAX_HEAD_BYTES];
+    loop {
+        let read = stream.peek(&mut buf).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if let Some(end) = buf[..read].windows(4).position(|w| w == b"\r\n\r\n") {
+            return Ok(Some(String::from_utf8_lossy(&buf[..end + 4]).into_owned()));
+        }
+        if read == buf.len() {
+            return Ok(None);
+        }
+        // Peek returns at once while the rest is in flight
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+async fn respond(
+    mut stream: TcpStream,
+    head_len: usize,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Consume the request so closing does not reset the connection
+    let mut head = vec![0u8; head_len];
+    stream.read_exact(&mut head).await?;
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn stream_audio(
+    stream: TcpStream,
+    peer: SocketAddr,
+    rate: u32,
+    server: &Server,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut socket = tokio_tungstenite::accept_async(stream).await?;
+    println!("[Remote] {} connected, streaming at {} Hz", peer, rate);
+
+    let start = *server.position.lock().unwrap();
+    let mut sender = FrameSender::new(rate, server.format, server.tx.clone()).starting_at(start);
+    let mut samples = Vec::new();
+
+    while let Some(message) = socket.next().await {
+        let bytes = match message {
+            Ok(Message::Binary(bytes)) => bytes,
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            // A phone locking its screen drops the socket without a close
+            Err(e) => {
+                eprintln!("[Remote] {} connection lost: {}", peer, e);
+                break;
+            }
+        };
+
+        samples.clear();
+        samples.extend(bytes.chunks_exact(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])));
+        sender.push(&samples);
+        *server.position.lock().unwrap() = sender.position();
+        if sender.is_closed() {
+            break;
+        }
+    }
+
+    sender.flush();
+    *server.position.lock().unwrap() = sender.position();
+    println!("[Remote] {} disconnected", peer);
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    /// The capture page
+    Page,
+    /// A WebSocket carrying PCM at this rate
+    Audio { rate: u32 },
+    /// Answer with this status line and close
+    Reject(&'static str),
+}
+
+fn route(head: &str, token: &str) -> Route {
+    let mut lines = head.lines();
+    let mut request = lines.next().unwrap_or_default().split(' ');
+    let (Some("GET"), Some(target)) = (request.next(), request.next()) else {
+        return Route::Reject("405 Method Not Allowed");
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let param = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    };
+
+    if path != "/" && path != "/ws" {
+        return Route::Reject("404 Not Found");
+    }
+    if !param("token").is_some_and(|given| tokens_match(given, token)) {
+        return Route::Reject("403 Forbidden");
+    }
+    if path == "/" {
+        return Route::Page;
+    }
+
+    let upgrade = lines.any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("upgrade:") && line.contains("websocket")
+    });
+    match param("rate").and_then(|rate| rate.parse().ok()) {
+        Some(rate) if upgrade && (8000..=192000).contains(&rate) => Route::Audio { rate },
+        _ => Route::Reject("400 Bad Request"),
+    }
+}
+
+/// Compare without stopping at the first difference, so response timing
+/// does not reveal how much of a guess was right
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    fn request(target: &str, upgrade: bool) -> String {
+        let upgrade = if upgrade { "Upgrade: websocket\r\nConnection: Upgrade\r\n" } else { "" };
+        format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1:8765\r\n{}\r\n", target, upgrade)
+    }
+
+    #[test]
+    fn routes_only_requests_with_the_pairing_token() {
+        let get = |target: &str, upgrade| route(&request(target, upgrade), TOKEN);
+
+        assert_eq!(get("/?token=0123456789abcdef", false), Route::Page);
+        assert_eq!(get("/ws?token=0123456789abcdef&rate=48000", true), Route::Audio { rate: 48000 });
+
+        assert_eq!(get("/", false), Route::Reject("403 Forbidden"));
+        assert_eq!(get("/?token=0123456789abcdee", false), Route::Reject("403 Forbidden"));
+        assert_eq!(get("/ws?token=0123&rate=48000", true), Route::Reject("403 Forbidden"));
+        assert_eq!(get("/ws?token=0123456789abcdef&rate=48000", false), Route::Reject("400 Bad Request"));
+        assert_eq!(get("/ws?token=0123456789abcdef&rate=1", true), Route::Reject("400 Bad Request"));
+        assert_eq!(get("/favicon.ico", false), Route::Reject("404 Not Found"));
+        assert_eq!(
+            route("POST /?token=0123456789abcdef HTTP/1.1\r\n\r\n", TOKEN),
+            Route::Reject("405 Method Not Allowed")
+        );
+    }
+
+    #[test]
+    fn binds_only_to_loopback_or_lan_addresses() {
+        for ip in ["127.0.0.1", "192.168.1.20", "10.0.0.5", "172.16.4.2", "::1", "fd12::1", "fe80::1"] {
+            assert!(check_bind_address(ip.parse().unwrap()).is_ok(), "{}", ip);
+        }
+        for ip in ["0.0.0.0", "::", "8.8.8.8", "2001:db8::1"] {
+            assert!(check_bind_address(ip.parse().unwrap()).is_err(), "{}", ip);
+        }
+    }
+}
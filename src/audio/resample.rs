@@ -0,0 +1,223 @@
+use std::f64::consts::PI;
+
+// Number of zero crossings of the sinc on each side of the kernel centre
+const ZERO_CROSSINGS: usize = 16;
+// Kernel table resolution (entries per input sample)
+const TABLE_OVERSAMPLE: usize = 128;
+// Keep the passband edge slightly below the output Nyquist frequency
+const ROLLOFF: f64 = 0.95;
+
+/// Streaming band-limited resampler (windowed-sinc interpolation).
+///
+/// Converts mono `i16` audio from the device rate to the pipeline rate.
+/// State is carried across calls, so chunks of any size can be fed in.
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    half_width: usize,
+    kernel: Vec<f32>,
+    buffer: Vec<f32>,
+    // Position of the next output sample in the input buffer, kept as an
+    // exact rational (index + frac / output_rate) to avoid drift
+    pos_index: usize,
+    pos_frac: u64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        assert!(input_rate > 0 && output_rate > 0, "sample rates must be non-zero");
+
+        // Low-pass below the lower of the two Nyquist frequencies
+        let cutoff = (output_rate as f64 / input_rate as f64).min(1.0) * ROLLOFF;
+        let half_width = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+        let kernel = Self::build_kernel(cutoff, half_width);
+
+        Self {
+            input_rate,
+            output_rate,
+            half_width,
+            kernel,
+            // Prime with silence so the first output lines up with the first input
+            buffer: vec![0.0; half_width],
+            pos_index: half_width,
+            pos_frac: 0,
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.input_rate == self.output_rate
+    }
+
+    pub fn process_into(&mut self, input: &[i16], output: &mut Vec<i16>) {
+        if self.is_passthrough() {
+            output.extend_from_slice(input);
+            return;
+        }
+
+        self.buffer
+            .extend(input.iter().map(|&s| s as f32 / i16::MAX as f32));
+
+        // Produce every output sample whose kernel is fully covered by input
+        while self.pos_index + self.half_width < self.buffer.len() {
+            let frac = self.pos_frac as f64 / self.output_rate as f64;
+            let value = self.interpolate(self.pos_index, frac);
+            output.push((value * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+
+            self.pos_frac += self.input_rate as u64;
+            self.pos_index += (self.pos_frac / self.output_rate as u64) as usize;
+            self.pos_frac %= self.output_rate as u64;
+        }
+
+        // Drop input that no future output sample can reach
+        let consumed = (self.pos_index + 1).saturating_sub(self.half_width);
+        let consumed = consumed.min(self.buffer.len());
+        self.buffer.drain(..consumed);
+        self.pos_index -= consumed;
+    }
+
+    fn interpolate(&self, index: usize, frac: f64) -> f32 {
+        let start = index + 1 - self.half_width;
+        let end = index + self.half_width;
+
+        let mut acc = 0.0f32;
+        for k in start..=end {
+            // Distance from the output instant to input sample k
+            let distance = (k as f64 - index as f64) - frac;
+            acc += self.buffer[k] * self.kernel_at(distance);
+        }
+        acc
+    }
+
+    fn kernel_at(&self, distance: f64) -> f32 {
+        let position = distance.abs() * TABLE_OVERSAMPLE as f64;
+        let i = position as usize;
+        if i + 1 >= self.kernel.len() {
+            return 0.0;
+        }
+        let t = (position - i as f64) as f32;
+        self.kernel[i] * (1.0 - t) + self.kernel[i + 1] * t
+    }
+
+    fn build_kernel(cutoff: f64, half_width: usize) -> Vec<f32> {
+        let len = half_width * TABLE_OVERSAMPLE + 1;
+        (0..len)
+            .map(|i| {
+                let x = i as f64 / TABLE_OVERSAMPLE as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * cutoff * x).sin() / (PI * cutoff * x)
+                };
+                // Blackman window over [-half_width, half_width]
+                let n = x / half_width as f64;
+                let window = 0.42 + 0.5 * (PI * n).cos() + 0.08 * (2.0 * PI * n).cos();
+                (cutoff * sinc * window) as f32
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(rate: u32, freq: f64, seconds: f64, amplitude: f64) -> Vec<i16> {
+        let len = (rate as f64 * seconds) as usize;
+        (0..len)
+            .map(|n| {
+                let t = n as f64 / rate as f64;
+                ((2.0 * PI * freq * t).sin() * amplitude * i16::MAX as f64) as i16
+            })
+            .collect()
+    }
+
+    // Estimate the dominant frequency from rising zero crossings
+    fn estimate_frequency(samples: &[i16], rate: u32) -> f64 {
+        let crossings: Vec<usize> = samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < 0 && w[1] >= 0)
+            .map(|(i, _)| i)
+            .collect();
+        let first = *crossings.first().unwrap();
+        let last = *crossings.last().unwrap();
+        (crossings.len() - 1) as f64 * rate as f64 / (last - first) as f64
+    }
+
+    fn rms(samples: &[i16]) -> f64 {
+        (samples
+            .iter()
+            .map(|&s| (s as f64 / i16::MAX as f64).powi(2))
+            .sum::<f64>()
+            / samples.len() as f64)
+            .sqrt()
+    }
+
+    fn resample_in_chunks(resampler: &mut Resampler, input: &[i16], chunk: usize) -> Vec<i16> {
+        let mut output = Vec::new();
+        for piece in input.chunks(chunk) {
+            resampler.process_into(piece, &mut output);
+        }
+        output
+    }
+
+    fn check_tone(input_rate: u32, freq: f64) {
+        let input = sine(input_rate, freq, 2.0, 0.5);
+        let mut resampler = Resampler::new(input_rate, 16000);
+        let output = resample_in_chunks(&mut resampler, &input, 441);
+
+        // Only the kernel lookahead may be held back
+        let expected = 32000usize;
+        let lookahead = resampler.half_width * 16000 / input_rate as usize + 1;
+        assert!(output.len() <= expected);
+        assert!(output.len() + lookahead >= expected, "got {} samples", output.len());
+
+        let settled = &output[1600..output.len() - 1600];
+        let measured = estimate_frequency(settled, 16000);
+        assert!((measured - freq).abs() < 1.0, "expected {} Hz, got {} Hz", freq, measured);
+
+        let level = rms(settled);
+        let expected_level = 0.5 / 2f64.sqrt();
+        assert!((level - expected_level).abs() < 0.01, "rms {}", level);
+    }
+
+    #[test]
+    fn resamples_sine_from_44100() {
+        check_tone(44100, 440.0);
+        check_tone(44100, 1000.0);
+        check_tone(44100, 3000.0);
+    }
+
+    #[test]
+    fn resamples_sine_from_48000() {
+        check_tone(48000, 440.0);
+        check_tone(48000, 1000.0);
+        check_tone(48000, 3000.0);
+    }
+
+    #[test]
+    fn rejects_tones_above_output_nyquist() {
+        // 12 kHz would alias to 4 kHz with nearest-sample decimation
+        let input = sine(48000, 12000.0, 1.0, 0.5);
+        let mut resampler = Resampler::new(48000, 16000);
+        let output = resample_in_chunks(&mut resampler, &input, input.len());
+        let settled = &output[800..output.len() - 800];
+        assert!(rms(settled) < 0.005, "alias leaked with rms {}", rms(settled));
+    }
+
+    #[test]
+    fn chunk_size_does_not_change_output() {
+        let input = sine(48000, 1000.0, 0.5, 0.5);
+        let whole = resample_in_chunks(&mut Resampler::new(48000, 16000), &input, input.len());
+        let chunked = resample_in_chunks(&mut Resampler::new(48000, 16000), &input, 97);
+        assert_eq!(whole, chunked);
+    }
+
+    #[test]
+    fn same_rate_is_passthrough() {
+        let input = sine(16000, 1000.0, 0.1, 0.5);
+        let mut resampler = Resampler::new(16000, 16000);
+        assert!(resampler.is_passthrough());
+        assert_eq!(resample_in_chunks(&mut resampler, &input, 160), input);
+    }
+}
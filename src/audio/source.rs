@@ -0,0 +1,228 @@
+use super::capture::AudioCapture;
+use super::device::{DeviceSelection, StreamPreferences};
+use super::downmix::ChannelMix;
+use super::meeting::MeetingSource;
+use super::remote::RemoteSource;
+use super::resample::Resampler;
+use super::stdin::StdinSource;
+use super::wav::WavFileSource;
+use crate::config::{Config, SourceKind};
+use crate::state::StateManager;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Producer of `i16` audio frames for the VAD and Deepgram pipeline: mono,
+/// or interleaved me/them channels from the meeting source
+pub trait AudioSource {
+    /// Short description for the startup log
+    fn describe(&self) -> String;
+
+    /// Rate and frame duration of the audio delivered on the channel
+    fn format(&self) -> FrameFormat;
+
+    /// Start delivering audio. Delivery stops when the receiver is closed,
+    /// when a live source is dropped or when a finite input ends.
+    fn start(&mut self, tx: mpsc::UnboundedSender<AudioFrame>) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+pub fn open(
+    config: &Config,
+    state_manager: Arc<StateManager>,
+) -> Result<Box<dyn AudioSource>, Box<dyn std::error::Error>> {
+    let format = FrameFormat::from_config(config);
+    let channel_mix = ChannelMix::parse(&config.audio.channel_mix)?;
+
+    let source: Box<dyn AudioSource> = match config.source.kind {
+        SourceKind::Microphone => Box::new(microphone(config, format, channel_mix, state_manager)?),
+        SourceKind::Wav => {
+            let path = config
+                .source
+                .path
+                .clone()
+                .ok_or("source.path must be set for the wav source")?;
+            Box::new(WavFileSource::new(path, config.source.pacing, format, channel_mix))
+        }
+        SourceKind::Stdin => Box::new(StdinSource::new(config.source.sample_rate, format)),
+        SourceKind::Remote => Box::new(RemoteSource::new(&config.remote, format)?),
+        SourceKind::Meeting => {
+            let me = microphone(config, format, channel_mix, state_manager)?;
+            // The far side keeps the device's own settings and averages its
+            // channels; stereo call audio carries the same voices on both.
+            // Only the microphone decides MicConflict: while the far side is
+            // down its channel is silent and dictation carries on.
+            let them = AudioCapture::new(
+                format,
+                DeviceSelection::from_config(config.meeting.device.as_deref()),
+                StreamPreferences::default(),
+                ChannelMix::Average,
+                Duration::from_millis(config.audio.stall_timeout_ms),
+                None,
+            );
+            let them = if config.meeting.loopback { them.loopback() } else { them };
+            Box::new(MeetingSource::new(Box::new(me), Box::new(them), format))
+        }
+    };
+
+    Ok(source)
+}
+
+fn microphone(
+    config: &Config,
+    format: FrameFormat,
+    channel_mix: ChannelMix,
+    state_manager: Arc<StateManager>,
+) -> Result<AudioCapture, Box<dyn std::error::Error>> {
+    Ok(AudioCapture::new(
+        format,
+        DeviceSelection::from_config(config.audio.device.as_deref()),
+        StreamPreferences::from_config(&config.audio)?,
+        channel_mix,
+        Duration::from_millis(config.audio.stall_timeout_ms),
+        Some(state_manager),
+    ))
+}
+
+/// Rate, duration and channel count of the frames every source delivers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameFormat {
+    pub sample_rate: u32,
+    pub frame_ms: u32,
+    /// 1 for every source except meeting capture, which delivers 2
+    pub channels: u16,
+}
+
+impl FrameFormat {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            sample_rate: config.audio.sample_rate,
+            frame_ms: config.audio.frame_ms,
+            channels: 1,
+        }
+    }
+
+    /// Samples per channel in one frame
+    pub fn frame_size(&self) -> usize {
+        (self.sample_rate as u64 * self.frame_ms as u64 / 1000) as usize
+    }
+
+    /// Interleaved samples in one frame
+    pub fn frame_len(&self) -> usize {
+        self.frame_size() * self.channels as usize
+    }
+}
+
+/// A fixed-duration block of audio at the pipeline rate, interleaved when
+/// the format has more than one channel
+#[derive(Debug, Clone)]
+pub struct AudioFrame {
+    /// Position of the first sample in the delivered stream
+    pub timestamp: Duration,
+    pub samples: Vec<i16>,
+}
+
+/// Resamples mono audio to the pipeline rate and sends it as frames of the
+/// configured duration
+pub struct FrameSender {
+    resampler: Resampler,
+    pending: Vec<i16>,
+    format: FrameFormat,
+    // Samples delivered so far, the clock for frame timestamps
+    position: u64,
+    tx: mpsc::UnboundedSender<AudioFrame>,
+}
+
+impl FrameSender {
+    pub fn new(input_rate: u32, format: FrameFormat, tx: mpsc::UnboundedSender<AudioFrame>) -> Self {
+        let resampler = Resampler::new(input_rate, format.sample_rate);
+        if !resampler.is_passthrough() {
+            println!("[Audio] Resampling {} Hz -> {} Hz", input_rate, format.sample_rate);
+        }
+
+        Self {
+            resampler,
+            pending: Vec::with_capacity(format.frame_size() * 2),
+            format,
+            position: 0,
+            tx,
+        }
+    }
+
+    /// Continue the timestamps of an earlier sender, e.g. after a device switch
+    pub fn starting_at(mut self, position: u64) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn push(&mut self, mono: &[i16]) {
+        self.resampler.process_into(mono, &mut self.pending);
+
+        // Send whole frames, keep the remainder for the next push
+        let frame_size = self.format.frame_size();
+        let mut sent = 0;
+        while self.pending.len() - sent >= frame_size {
+            let samples = self.pending[sent..sent + frame_size].to_vec();
+            self.send(samples);
+            sent += frame_size;
+        }
+        self.pending.drain(..sent);
+    }
+
+    /// Send whatever is left as a final frame, padded with silence
+    pub fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            let mut samples = std::mem::take(&mut self.pending);
+            samples.resize(self.format.frame_size(), 0);
+            self.send(samples);
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    fn send(&mut self, samples: Vec<i16>) {
+        let timestamp = Duration::from_secs_f64(self.position as f64 / self.format.sample_rate as f64);
+        self.position += samples.len() as u64;
+        let _ = self.tx.send(AudioFrame { timestamp, samples });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delivers_fixed_size_frames_with_timestamps() {
+        let format = FrameFormat {
+            sample_rate: 16000,
+            frame_ms: 20,
+            channels: 1,
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut sender = FrameSender::new(16000, format, tx);
+
+        // Uneven pushes still come out as 320-sample frames
+        sender.push(&[1; 500]);
+        sender.push(&[2; 500]);
+        sender.flush();
+
+        let mut frames = Vec::new();
+        while let Ok(frame) = rx.try_recv() {
+            frames.push(frame);
+        }
+
+        assert_eq!(frames.len(), 4);
+        assert!(frames.iter().all(|f| f.samples.len() == 320));
+        let timestamps: Vec<u128> = frames.iter().map(|f| f.timestamp.as_millis()).collect();
+        assert_eq!(timestamps, vec![0, 20, 40, 60]);
+
+        // The final frame holds the 40 leftover samples, then silence
+        assert_eq!(&frames[3].samples[..40], &[2; 40]);
+        assert!(frames[3].samples[40..].iter().all(|&s| s == 0));
+    }
+}
@@ -0,0 +1,126 @@
+use super::source::{AudioFrame, AudioSource, FrameFormat, FrameSender};
+use std::io::Read;
+use tokio::sync::mpsc;
+
+/// Reads raw mono signed 16-bit little-endian PCM from standard input,
+/// e.g. `arecord -f S16_LE -c 1 -r 16000 | dictation-rs --source stdin`
+pub struct StdinSource {
+    input_rate: u32,
+    format: FrameFormat,
+}
+
+impl StdinSource {
+    pub fn new(input_rate: u32, format: FrameFormat) -> Self {
+        Self {
+            input_rate,
+            format,
+        }
+    }
+}
+
+impl AudioSource for StdinSource {
+    fn describe(&self) -> String {
+        format!("raw s16le from stdin at {} Hz", self.input_rate)
+    }
+
+    fn format(&self) -> FrameFormat {
+        self.format
+    }
+
+    fn start(&mut self, tx: mpsc::UnboundedSender<AudioFrame>) -> Result<(), Box<dyn std::error::Error>> {
+        let sender = FrameSender::new(self.input_rate, self.format, tx);
+        let block_bytes = (self.input_rate / 10) as usize * 2;
+
+        std::thread::Builder::new()
+            .name("stdin-source".to_string())
+            .spawn(move || {
+                if let Err(e) = read_stdin(sender, block_bytes) {
+                    eprintln!("[Audio] stdin read error: {}", e);
+                }
+            })?;
+
+        Ok(())
+    }
+}
+
+fn read_stdin(sender: FrameSender, block_bytes: usize) -> std::io::Result<()> {
+    read_pcm(std::io::stdin().lock(), sender, block_bytes)?;
+    println!("[Audio] End of stdin");
+    Ok(())
+}
+
+/// Send raw s16le audio from `input` until it ends
+fn read_pcm(mut input: impl Read, mut sender: FrameSender, block_bytes: usize) -> std::io::Result<()> {
+    let mut bytes = vec![0u8; block_bytes];
+    let mut samples = Vec::with_capacity(block_bytes / 2);
+    // An odd byte left over from a short read, completed by the next one
+    let mut carry: Option<u8> = None;
+
+    loop {
+        let read = match input.read(&mut bytes) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        samples.clear();
+        let mut data = &bytes[..read];
+        if let Some(low) = carry.take() {
+            samples.push(i16::from_le_bytes([low, data[0]]));
+            data = &data[1..];
+        }
+        let mut pairs = data.chunks_exact(2);
+        samples.extend(pairs.by_ref().map(|pair| i16::from_le_bytes([pair[0], pair[1]])));
+        carry = pairs.remainder().first().copied();
+
+        sender.push(&samples);
+        if sender.is_closed() {
+            return Ok(());
+        }
+    }
+
+    sender.flush();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out at most `chunk` bytes per read, like a pipe
+    struct Trickle<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn joins_samples_split_across_reads_and_resamples() {
+        // 150 ms at 8 kHz, read 3 bytes at a time, into 100 ms frames at 16 kHz
+        let samples: Vec<i16> = (0..1200)
+            .map(|i| (10000.0 * (2.0 * std::f32::consts::PI * 200.0 * i as f32 / 8000.0).sin()) as i16)
+            .collect();
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let format = FrameFormat { sample_rate: 16000, frame_ms: 100, channels: 1 };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        read_pcm(Trickle { data: &bytes, chunk: 3 }, FrameSender::new(8000, format, tx), 1600).unwrap();
+
+        let frames: Vec<AudioFrame> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|frame| frame.samples.len() == 1600));
+        // Upsampling keeps the waveform: every other output sample is an input sample
+        for i in [100, 333, 700] {
+            assert!((frames[0].samples[2 * i] - samples[i]).abs() < 300);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+#[cfg(feature = "opus")]
+use super::opus::{OggOpusEncoder, OpusEncoder};
+use crate::config::AudioEncoding;
+
+/// Turns pipeline audio into the WebSocket messages sent to Deepgram
+pub enum UplinkEncoder {
+    /// Raw little-endian 16-bit PCM
+    Linear16 { sample_rate: u32 },
+    /// One raw Opus packet per message
+    #[cfg(feature = "opus")]
+    Opus(OpusEncoder, u32),
+    /// A continuous Ogg/Opus stream, sent page by page
+    #[cfg(feature = "opus")]
+    OggOpus(OggOpusEncoder),
+}
+
+impl UplinkEncoder {
+    #[cfg_attr(not(feature = "opus"), allow(unused_variables))]
+    pub fn new(encoding: AudioEncoding, sample_rate: u32, bitrate: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        match encoding {
+            AudioEncoding::Linear16 => Ok(UplinkEncoder::Linear16 { sample_rate }),
+            #[cfg(feature = "opus")]
+            AudioEncoding::Opus => Ok(UplinkEncoder::Opus(OpusEncoder::new(sample_rate, bitrate)?, sample_rate)),
+            #[cfg(feature = "opus")]
+            AudioEncoding::OggOpus => Ok(UplinkEncoder::OggOpus(OggOpusEncoder::new(sample_rate, bitrate)?)),
+            #[cfg(not(feature = "opus"))]
+            AudioEncoding::Opus | AudioEncoding::OggOpus => {
+                Err("Opus encoding needs a build with the \"opus\" feature".into())
+            }
+        }
+    }
+
+    /// Query parameters describing the audio to Deepgram
+    pub fn query(&self) -> String {
+        match self {
+            UplinkEncoder::Linear16 { sample_rate } => format!("encoding=linear16&sample_rate={}", sample_rate),
+            #[cfg(feature = "opus")]
+            UplinkEncoder::Opus(_, sample_rate) => format!("encoding=opus&sample_rate={}", sample_rate),
+            // Containerized audio describes itself in its headers
+            #[cfg(feature = "opus")]
+            UplinkEncoder::OggOpus(_) => String::new(),
+        }
+    }
+
+    pub fn encode(&mut self, samples: &[i16]) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+        match self {
+            UplinkEncoder::Linear16 { .. } => {
+                Ok(vec![samples.iter().flat_map(|&sample| sample.to_le_bytes()).collect()])
+            }
+            #[cfg(feature = "opus")]
+            UplinkEncoder::Opus(encoder, _) => Ok(encoder.encode(samples)?),
+            #[cfg(feature = "opus")]
+            UplinkEncoder::OggOpus(encoder) => Ok(non_empty(encoder.encode(samples)?)),
+        }
+    }
+
+    /// Messages that flush buffered audio at the end of the stream
+    pub fn finish(&mut self) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+        match self {
+            UplinkEncoder::Linear16 { .. } => Ok(Vec::new()),
+            #[cfg(feature = "opus")]
+            UplinkEncoder::Opus(encoder, _) => Ok(encoder.flush()?.into_iter().collect()),
+            #[cfg(feature = "opus")]
+            UplinkEncoder::OggOpus(encoder) => Ok(non_empty(encoder.finish()?)),
+        }
+    }
+}
+
+#[cfg(feature = "opus")]
+fn non_empty(bytes: Vec<u8>) -> Vec<Vec<u8>> {
+    if bytes.is_empty() {
+        Vec::new()
+    } else {
+        vec![bytes]
+    }
+}
@@ -0,0 +1,193 @@
+use super::{Segmenter, Vad, VadEvent};
+use crate::config::VadConfig;
+
+/// Analysis block; smoothing and floor tracking run per block so they
+/// behave the same whatever the frame size
+const BLOCK_MS: u32 = 10;
+/// Levels are clamped here so digital silence cannot drag the floor to -inf
+const MIN_LEVEL_DB: f32 = -100.0;
+/// Time constant of the floor following quieter input: short, so pauses
+/// between words reveal the background
+const FLOOR_FALL_MS: f32 = 100.0;
+/// Fastest the floor follows louder input: slow enough that speech barely
+/// lifts it, fast enough that a fan switched on is absorbed within seconds
+const FLOOR_RISE_DB_PER_S: f32 = 0.5;
+
+/// Speech is input louder than the estimated background noise floor by a
+/// margin in dB, so the threshold follows the room and the microphone
+pub struct AdaptiveVad {
+    margin_db: f32,
+    hysteresis_db: f32,
+    attack_ms: u64,
+    release_ms: u64,
+    block_size: usize,
+    // Per-block smoothing coefficients and floor rise
+    attack: f32,
+    release: f32,
+    floor_fall: f32,
+    floor_rise_db: f32,
+    // Smoothed level and noise floor in dBFS, unset until the first block
+    level_db: Option<f32>,
+    floor_db: Option<f32>,
+    segmenter: Segmenter,
+}
+
+impl AdaptiveVad {
+    pub fn new(config: &VadConfig, sample_rate: u32, silence_threshold_ms: u64) -> Self {
+        let coefficient = |ms: f32| (-(BLOCK_MS as f32) / ms.max(1.0)).exp();
+        Self {
+            margin_db: config.margin_db,
+            hysteresis_db: config.hysteresis_db,
+            attack_ms: config.attack_ms,
+            release_ms: config.release_ms,
+            block_size: (sample_rate * BLOCK_MS / 1000) as usize,
+            attack: coefficient(config.attack_ms as f32),
+            release: coefficient(config.release_ms as f32),
+            floor_fall: coefficient(FLOOR_FALL_MS),
+            floor_rise_db: FLOOR_RISE_DB_PER_S * BLOCK_MS as f32 / 1000.0,
+            level_db: None,
+            floor_db: None,
+            segmenter: Segmenter::new(config, sample_rate, silence_threshold_ms),
+        }
+    }
+
+    /// Update the estimates with one block, returning whether it is speech
+    fn process_block(&mut self, block: &[i16]) -> bool {
+        let mean_square = block
+            .iter()
+            .map(|&s| {
+                let x = s as f32 / 32768.0;
+                x * x
+            })
+            .sum::<f32>()
+            / block.len() as f32;
+        let block_db = (10.0 * mean_square.log10()).max(MIN_LEVEL_DB);
+
+        // Attack/release smoothing keeps clicks from triggering and bridges
+        // the dips between syllables
+        let level = match self.level_db {
+            Some(level) => {
+                let coefficient = if block_db > level { self.attack } else { self.release };
+                coefficient * level + (1.0 - coefficient) * block_db
+            }
+            None => block_db,
+        };
+        self.level_db = Some(level);
+
+        let floor = match self.floor_db {
+            Some(floor) if block_db < floor => self.floor_fall * floor + (1.0 - self.floor_fall) * block_db,
+            Some(floor) => (floor + self.floor_rise_db).min(block_db),
+            None => block_db,
+        };
+        self.floor_db = Some(floor);
+
+        let margin = if self.segmenter.is_speaking() { self.margin_db - self.hysteresis_db } else { self.margin_db };
+        level > floor + margin
+    }
+}
+
+impl Vad for AdaptiveVad {
+    fn describe(&self) -> String {
+        format!(
+            "adaptive ({} dB over the noise floor, {} ms attack, {} ms release)",
+            self.margin_db, self.attack_ms, self.release_ms
+        )
+    }
+
+    fn process(&mut self, audio_chunk: &[i16]) -> VadEvent {
+        let mut is_speech = false;
+        for block in audio_chunk.chunks(self.block_size.max(1)) {
+            is_speech |= self.process_block(block);
+        }
+        self.segmenter.update(is_speech, audio_chunk.len())
+    }
+
+    fn heard_speech(&self) -> bool {
+        self.segmenter.heard_speech()
+    }
+
+    fn reset(&mut self) {
+        // The floor describes the room, not the utterance, so it is kept
+        self.segmenter.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const RATE: u32 = 16000;
+
+    fn amplitude(db: f32) -> f32 {
+        32768.0 * 10f32.powf(db / 20.0)
+    }
+
+    /// Uniform white noise at an RMS level in dBFS
+    fn noise(db: f32, seconds: f32, seed: &mut u32) -> Vec<i16> {
+        let peak = amplitude(db) * 3f32.sqrt();
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|_| {
+                *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                ((*seed >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0) * peak
+            })
+            .map(|x| x as i16)
+            .collect()
+    }
+
+    fn add_tone(signal: &mut [i16], db: f32) {
+        let peak = amplitude(db) * 2f32.sqrt();
+        for (i, s) in signal.iter_mut().enumerate() {
+            let tone = peak * (2.0 * std::f32::consts::PI * 300.0 * i as f32 / RATE as f32).sin();
+            *s = (*s as f32 + tone).clamp(-32768.0, 32767.0) as i16;
+        }
+    }
+
+    fn events(vad: &mut AdaptiveVad, signal: &[i16]) -> Vec<VadEvent> {
+        signal.chunks(RATE as usize / 10).map(|frame| vad.process(frame)).collect()
+    }
+
+    fn detector() -> AdaptiveVad {
+        AdaptiveVad::new(&Config::default().vad, RATE, 1000)
+    }
+
+    #[test]
+    fn triggers_relative_to_each_rooms_noise_floor() {
+        let mut seed = 1;
+        for floor_db in [-65.0, -40.0] {
+            let mut vad = detector();
+            let background = noise(floor_db, 3.0, &mut seed);
+            assert!(events(&mut vad, &background).iter().all(|&e| e == VadEvent::Silence));
+            let estimate = vad.floor_db.unwrap();
+            assert!((estimate - floor_db).abs() < 3.0, "floor {} estimated at {:.1}", floor_db, estimate);
+
+            // Speech 20 dB above whatever the room's background is
+            let mut speech = noise(floor_db, 0.3, &mut seed);
+            add_tone(&mut speech, floor_db + 20.0);
+            assert!(events(&mut vad, &speech).contains(&VadEvent::SpeechStarted));
+        }
+    }
+
+    #[test]
+    fn ignores_keyboard_clicks() {
+        let mut seed = 7;
+        let mut signal = noise(-60.0, 3.0, &mut seed);
+
+        // 5 ms clicks at -20 dBFS, one every 200 ms
+        for start in (RATE as usize..signal.len()).step_by(RATE as usize / 5) {
+            for s in &mut signal[start..start + 80] {
+                *s = if *s >= 0 { amplitude(-20.0) as i16 } else { -amplitude(-20.0) as i16 };
+            }
+        }
+
+        // Without a minimum speech duration only attack smoothing holds them off
+        let mut config = Config::default().vad;
+        config.min_speech_ms = 0;
+        let mut vad = AdaptiveVad::new(&config, RATE, 1000);
+        assert!(events(&mut vad, &signal).iter().all(|&e| e == VadEvent::Silence));
+
+        config.attack_ms = 1;
+        let mut vad = AdaptiveVad::new(&config, RATE, 1000);
+        assert!(events(&mut vad, &signal).contains(&VadEvent::SpeechStarted));
+    }
+}
@@ -0,0 +1,58 @@
+use super::{stop_factor, Segmenter, Vad, VadEvent};
+use crate::config::VadConfig;
+
+/// Speech is any frame whose RMS level is above a fixed threshold
+pub struct EnergyVad {
+    energy_threshold: f32,
+    stop_threshold: f32,
+    segmenter: Segmenter,
+}
+
+impl EnergyVad {
+    pub fn new(config: &VadConfig, sample_rate: u32, silence_threshold_ms: u64) -> Self {
+        Self {
+            energy_threshold: config.energy_threshold,
+            stop_threshold: config.energy_threshold * stop_factor(config.hysteresis_db),
+            segmenter: Segmenter::new(config, sample_rate, silence_threshold_ms),
+        }
+    }
+
+    fn detect_speech(&self, audio_chunk: &[i16]) -> bool {
+        if audio_chunk.is_empty() {
+            return false;
+        }
+
+        // Calculate RMS energy
+        let rms = (audio_chunk
+            .iter()
+            .map(|&s| {
+                let normalized = s as f32 / i16::MAX as f32;
+                normalized * normalized
+            })
+            .sum::<f32>()
+            / audio_chunk.len() as f32)
+            .sqrt();
+
+        let threshold = if self.segmenter.is_speaking() { self.stop_threshold } else { self.energy_threshold };
+        rms > threshold
+    }
+}
+
+impl Vad for EnergyVad {
+    fn describe(&self) -> String {
+        format!("energy (threshold {}, stop at {:.4})", self.energy_threshold, self.stop_threshold)
+    }
+
+    fn process(&mut self, audio_chunk: &[i16]) -> VadEvent {
+        let is_speech = self.detect_speech(audio_chunk);
+        self.segmenter.update(is_speech, audio_chunk.len())
+    }
+
+    fn heard_speech(&self) -> bool {
+        self.segmenter.heard_speech()
+    }
+
+    fn reset(&mut self) {
+        self.segmenter.reset();
+    }
+}
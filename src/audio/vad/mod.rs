@@ -0,0 +1,236 @@
+mod adaptive;
+mod energy;
+#[cfg(feature = "neural")]
+mod neural;
+mod spectral;
+
+pub use adaptive::AdaptiveVad;
+pub use energy::EnergyVad;
+#[cfg(feature = "neural")]
+pub use neural::NeuralVad;
+pub use spectral::SpectralVad;
+
+use crate::config::{Config, VadConfig, VadKind};
+
+/// Turns frames of mono pipeline audio into speech events. Implementations
+/// are chosen with `[vad] kind`; the manager only sees this trait.
+pub trait Vad: Send {
+    /// Name and main settings, for the startup log
+    fn describe(&self) -> String;
+
+    /// Classify the next frame
+    fn process(&mut self, audio_chunk: &[i16]) -> VadEvent;
+
+    /// Whether the last frame was speech, even inside an utterance or too
+    /// short to start one
+    fn heard_speech(&self) -> bool;
+
+    /// Forget the current utterance, e.g. after a stream was closed
+    fn reset(&mut self);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VadEvent {
+    SpeechStarted,
+    Speaking,
+    SilenceDetected,
+    Silence,
+}
+
+/// The detector selected in the config; only the neural one can fail, when
+/// its model cannot be loaded
+pub fn from_config(config: &Config) -> Result<Box<dyn Vad>, Box<dyn std::error::Error>> {
+    let (rate, silence_ms) = (config.audio.sample_rate, config.audio.silence_threshold_ms);
+    Ok(match config.vad.kind {
+        VadKind::Energy => Box::new(EnergyVad::new(&config.vad, rate, silence_ms)),
+        VadKind::Adaptive => Box::new(AdaptiveVad::new(&config.vad, rate, silence_ms)),
+        VadKind::Spectral => Box::new(SpectralVad::new(&config.vad, rate, silence_ms)),
+        #[cfg(feature = "neural")]
+        VadKind::Neural => {
+            let model_path = config.vad.model_path.as_deref().ok_or("vad.model_path is not set")?;
+            Box::new(NeuralVad::new(&config.vad, model_path, rate, silence_ms)?)
+        }
+        #[cfg(not(feature = "neural"))]
+        VadKind::Neural => return Err("The neural VAD needs a build with the \"neural\" feature".into()),
+    })
+}
+
+/// Factor applied to an RMS threshold once speech has started, so speech
+/// only stops `hysteresis_db` below the level that started it
+fn stop_factor(hysteresis_db: f32) -> f32 {
+    10f32.powf(-hysteresis_db / 20.0)
+}
+
+/// Turns per-frame speech decisions into events, shared by the detectors.
+/// An utterance starts once speech has lasted `min_speech_ms`, counting
+/// across gaps no longer than `hangover_ms`, and ends once no speech was
+/// seen for the silence threshold. Time is counted in samples, so the same
+/// audio always gives the same events, however fast it is processed.
+struct Segmenter {
+    min_speech: u64,
+    hangover: u64,
+    silence_threshold: u64,
+    // Samples seen so far, and the position where the last speech frame ended
+    position: u64,
+    last_speech: Option<u64>,
+    // Speech counted towards min_speech in the current run
+    speech_run: u64,
+    is_speaking: bool,
+}
+
+impl Segmenter {
+    fn new(config: &VadConfig, sample_rate: u32, silence_threshold_ms: u64) -> Self {
+        let samples = |ms: u64| ms * sample_rate as u64 / 1000;
+        Self {
+            min_speech: samples(config.min_speech_ms),
+            hangover: samples(config.hangover_ms),
+            silence_threshold: samples(silence_threshold_ms),
+            position: 0,
+            last_speech: None,
+            speech_run: 0,
+            is_speaking: false,
+        }
+    }
+
+    /// Whether an utterance is open; detectors use their stop threshold then
+    fn is_speaking(&self) -> bool {
+        self.is_speaking
+    }
+
+    fn heard_speech(&self) -> bool {
+        self.last_speech == Some(self.position)
+    }
+
+    /// Advance by a frame of `samples` samples that was or was not speech
+    fn update(&mut self, is_speech: bool, samples: usize) -> VadEvent {
+        let frame_start = self.position;
+        self.position += samples as u64;
+
+        if is_speech {
+            // A gap longer than the hangover starts a new run
+            let bridged = self.last_speech.is_some_and(|last| frame_start - last <= self.hangover);
+            self.speech_run = if bridged { self.speech_run + samples as u64 } else { samples as u64 };
+            self.last_speech = Some(self.position);
+
+            if self.is_speaking {
+                return VadEvent::Speaking;
+            }
+            if self.speech_run >= self.min_speech {
+                self.is_speaking = true;
+                return VadEvent::SpeechStarted;
+            }
+            VadEvent::Silence
+        } else {
+            // Silence detected
+            if let Some(last_speech) = self.last_speech {
+                let silence = self.position - last_speech;
+
+                if self.is_speaking && silence > self.silence_threshold {
+                    self.is_speaking = false;
+                    return VadEvent::SilenceDetected;
+                }
+            }
+
+            if self.is_speaking {
+                return VadEvent::Speaking;
+            }
+            VadEvent::Silence
+        }
+    }
+
+    fn reset(&mut self) {
+        self.is_speaking = false;
+        self.last_speech = None;
+        self.speech_run = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+    const FRAME: usize = RATE as usize / 10;
+
+    fn segmenter(min_speech_ms: u64, hangover_ms: u64, silence_threshold_ms: u64) -> Segmenter {
+        let config = VadConfig {
+            min_speech_ms,
+            hangover_ms,
+            ..Config::default().vad
+        };
+        Segmenter::new(&config, RATE, silence_threshold_ms)
+    }
+
+    #[test]
+    fn silence_is_measured_in_samples() {
+        let mut segmenter = segmenter(0, 0, 500);
+        assert_eq!(segmenter.update(true, FRAME), VadEvent::SpeechStarted);
+
+        // Exactly the threshold keeps the utterance open, one frame more ends it
+        for _ in 0..5 {
+            assert_eq!(segmenter.update(false, FRAME), VadEvent::Speaking);
+        }
+        assert_eq!(segmenter.update(false, FRAME), VadEvent::SilenceDetected);
+        assert_eq!(segmenter.update(false, FRAME), VadEvent::Silence);
+    }
+
+    #[test]
+    fn hours_of_audio_give_the_same_events_every_time() {
+        // Three hours of 20 ms frames: speech bursts of pseudo-random length
+        // and gaps, some shorter than the silence threshold
+        let run = || {
+            let mut segmenter = segmenter(0, 0, 700);
+            let mut seed: u32 = 42;
+            let mut events = Vec::new();
+            let mut speech = false;
+            let mut left = 0;
+            let mut last_speech_frame = 0;
+            for frame in 0..3 * 3600 * 50 {
+                if left == 0 {
+                    seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    speech = !speech;
+                    left = 1 + (seed >> 16) % 150;
+                }
+                left -= 1;
+                if speech {
+                    last_speech_frame = frame;
+                }
+                match segmenter.update(speech, RATE as usize / 50) {
+                    VadEvent::SilenceDetected => {
+                        // The first frame more than 700 ms after the last speech
+                        assert_eq!(frame - last_speech_frame, 36);
+                        events.push((frame, VadEvent::SilenceDetected));
+                    }
+                    VadEvent::SpeechStarted => events.push((frame, VadEvent::SpeechStarted)),
+                    _ => {}
+                }
+            }
+            events
+        };
+
+        let events = run();
+        assert!(events.len() > 1000);
+        assert_eq!(events, run());
+    }
+
+    #[test]
+    fn a_single_loud_frame_does_not_start_speech() {
+        let mut segmenter = segmenter(200, 100, 500);
+        assert_eq!(segmenter.update(true, FRAME), VadEvent::Silence);
+        assert_eq!(segmenter.update(false, FRAME), VadEvent::Silence);
+        assert_eq!(segmenter.update(false, FRAME), VadEvent::Silence);
+
+        // After a gap longer than the hangover the count starts over
+        assert_eq!(segmenter.update(true, FRAME), VadEvent::Silence);
+        assert_eq!(segmenter.update(true, FRAME), VadEvent::SpeechStarted);
+    }
+
+    #[test]
+    fn hangover_bridges_short_dips_while_counting_speech() {
+        let mut segmenter = segmenter(300, 100, 500);
+        assert_eq!(segmenter.update(true, FRAME), VadEvent::Silence);
+        assert_eq!(segmenter.update(false, FRAME), VadEvent::Silence);
+        assert_eq!(segmenter.update(true, FRAME), VadEvent::Silence);
+        assert_eq!(segmenter.update(true, FRAME), VadEvent::SpeechStarted);
+    }
+}
@@ -0,0 +1,271 @@
+use super::{Segmenter, Vad, VadEvent};
+use crate::config::VadConfig;
+use std::path::Path;
+use tract_onnx::prelude::*;
+
+/// Samples per model call at 16 kHz, half of it at 8 kHz
+const WINDOW_16K: usize = 512;
+/// Samples of the previous window that models with a single `state` input
+/// (Silero v5) expect in front of each window
+const CONTEXT_16K: usize = 64;
+
+/// What the model expects at each of its inputs
+enum Slot {
+    Audio,
+    SampleRate,
+    State,
+}
+
+/// Speech probability from a Silero-style ONNX model run on the CPU: a
+/// recurrent network fed fixed windows of 8 or 16 kHz audio that returns
+/// the probability along with its next state
+pub struct NeuralVad {
+    model: TypedRunnableModel<TypedModel>,
+    model_name: String,
+    slots: Vec<Slot>,
+    sample_rate: u32,
+    window: usize,
+    context: usize,
+    // Recurrent state, one tensor per state input, fed back after each window
+    state: Vec<Tensor>,
+    // Context followed by the window being filled, as floats
+    buffer: Vec<f32>,
+    // Probabilities of the windows completed by the current frame
+    probabilities: Vec<f32>,
+    failed: bool,
+    hysteresis: Hysteresis,
+}
+
+/// Turns the model's per-window probabilities into events: speech starts
+/// above `start_probability` and, once an utterance is open, continues
+/// until the probability falls to `stop_probability`
+struct Hysteresis {
+    start_probability: f32,
+    stop_probability: f32,
+    // Last window's probability, reused by frames that complete no window
+    probability: f32,
+    segmenter: Segmenter,
+}
+
+impl Hysteresis {
+    fn new(config: &VadConfig, sample_rate: u32, silence_threshold_ms: u64) -> Self {
+        Self {
+            start_probability: config.start_probability,
+            stop_probability: config.stop_probability,
+            probability: 0.0,
+            segmenter: Segmenter::new(config, sample_rate, silence_threshold_ms),
+        }
+    }
+
+    /// Decide a frame of `samples` samples from the probabilities of the
+    /// windows it completed; any window above the threshold makes it speech
+    fn update(&mut self, probabilities: &[f32], samples: usize) -> VadEvent {
+        let threshold = if self.segmenter.is_speaking() { self.stop_probability } else { self.start_probability };
+        let is_speech = match probabilities.last() {
+            Some(&last) => {
+                self.probability = last;
+                probabilities.iter().any(|&probability| probability > threshold)
+            }
+            None => self.probability > threshold,
+        };
+        self.segmenter.update(is_speech, samples)
+    }
+}
+
+impl NeuralVad {
+    pub fn new(
+        config: &VadConfig,
+        model_path: &Path,
+        sample_rate: u32,
+        silence_threshold_ms: u64,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut model = tract_onnx::onnx()
+            .model_for_path(model_path)
+            .map_err(|e| format!("Cannot load VAD model {}: {}", model_path.display(), e))?;
+
+        let names: Vec<String> = model
+            .input_outlets()?
+            .iter()
+            .map(|outlet| model.node(outlet.node).name.clone())
+            .collect();
+        let window = WINDOW_16K * sample_rate as usize / 16000;
+        let context = if names.iter().any(|name| name == "state") {
+            CONTEXT_16K * sample_rate as usize / 16000
+        } else {
+            0
+        };
+
+        // Fix every input's type and shape so the model can be optimized
+        let mut slots = Vec::new();
+        let mut state = Vec::new();
+        for (ix, name) in names.iter().enumerate() {
+            let fact = match name.as_str() {
+                "input" => {
+                    slots.push(Slot::Audio);
+                    InferenceFact::dt_shape(f32::datum_type(), [1, window + context])
+                }
+                "sr" => {
+                    slots.push(Slot::SampleRate);
+                    InferenceFact::dt_shape(i64::datum_type(), [0usize; 0])
+                }
+                "state" | "h" | "c" => {
+                    let shape = if name == "state" { [2, 1, 128] } else { [2, 1, 64] };
+                    slots.push(Slot::State);
+                    state.push(Tensor::zero::<f32>(&shape)?);
+                    InferenceFact::dt_shape(f32::datum_type(), shape)
+                }
+                other => {
+                    return Err(format!(
+                        "VAD model {} has an unexpected input \"{}\"; expected a Silero VAD model",
+                        model_path.display(),
+                        other
+                    )
+                    .into())
+                }
+            };
+            model.set_input_fact(ix, fact)?;
+        }
+        let model = model.into_optimized()?.into_runnable()?;
+
+        Ok(Self {
+            model,
+            model_name: model_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            slots,
+            sample_rate,
+            window,
+            context,
+            state,
+            buffer: vec![0.0; context],
+            probabilities: Vec::new(),
+            failed: false,
+            hysteresis: Hysteresis::new(config, sample_rate, silence_threshold_ms),
+        })
+    }
+
+    /// Run the model on the buffered context and window
+    fn infer(&mut self) -> TractResult<f32> {
+        let mut states = self.state.drain(..);
+        let mut inputs = TVec::new();
+        for slot in &self.slots {
+            let tensor = match slot {
+                Slot::Audio => Tensor::from_shape(&[1, self.context + self.window], &self.buffer[..self.context + self.window])?,
+                Slot::SampleRate => tensor0(self.sample_rate as i64),
+                Slot::State => states.next().ok_or_else(|| TractError::msg("missing state"))?,
+            };
+            inputs.push(tensor.into());
+        }
+        drop(states);
+
+        let mut outputs = self.model.run(inputs)?.into_iter();
+        let probability = outputs
+            .next()
+            .ok_or_else(|| TractError::msg("model returned no outputs"))?
+            .as_slice::<f32>()?
+            .first()
+            .copied()
+            .unwrap_or(0.0);
+        // The outputs after the probability are the next state, in input order
+        self.state = outputs.map(|output| output.into_tensor()).collect();
+        Ok(probability)
+    }
+}
+
+impl Vad for NeuralVad {
+    fn describe(&self) -> String {
+        format!(
+            "neural ({}, speech above {} until below {})",
+            self.model_name, self.hysteresis.start_probability, self.hysteresis.stop_probability
+        )
+    }
+
+    fn process(&mut self, audio_chunk: &[i16]) -> VadEvent {
+        self.probabilities.clear();
+        self.buffer.extend(audio_chunk.iter().map(|&s| s as f32 / 32768.0));
+        while !self.failed && self.buffer.len() >= self.context + self.window {
+            match self.infer() {
+                Ok(probability) => self.probabilities.push(probability),
+                Err(e) => {
+                    // Reported once; the detector then only hears silence
+                    eprintln!("[VAD] Model failed: {}", e);
+                    self.failed = true;
+                    self.probabilities.push(0.0);
+                }
+            }
+            // The end of this window is the context of the next
+            self.buffer.drain(..self.window);
+        }
+        if self.failed {
+            self.buffer.clear();
+        }
+
+        self.hysteresis.update(&self.probabilities, audio_chunk.len())
+    }
+
+    fn heard_speech(&self) -> bool {
+        self.hysteresis.segmenter.heard_speech()
+    }
+
+    fn reset(&mut self) {
+        // The model state follows the audio, which continues after an utterance
+        self.hysteresis.segmenter.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn reports_a_missing_model() {
+        let error = NeuralVad::new(&Config::default().vad, Path::new("no-such-model.onnx"), 16000, 1000)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("no-such-model.onnx"));
+    }
+
+    /// Events for 100 ms frames at 16 kHz, each completing the windows given
+    fn events(frames: &[&[f32]]) -> Vec<VadEvent> {
+        let mut config = Config::default().vad;
+        config.start_probability = 0.5;
+        config.stop_probability = 0.35;
+        config.min_speech_ms = 200;
+        config.hangover_ms = 0;
+        let mut hysteresis = Hysteresis::new(&config, 16000, 300);
+        frames.iter().map(|windows| hysteresis.update(windows, 1600)).collect()
+    }
+
+    #[test]
+    fn starts_above_the_start_probability_and_stops_below_the_stop_probability() {
+        use VadEvent::*;
+        let events = events(&[
+            &[0.1, 0.45, 0.3],
+            // One window above 0.5 makes the frame speech; two frames start it
+            &[0.2, 0.6, 0.1],
+            &[0.7, 0.8, 0.9],
+            // 0.4 would not have started speech but keeps it going
+            &[0.4, 0.45, 0.4],
+            &[0.3, 0.2, 0.1],
+            &[0.2, 0.1, 0.1],
+            &[0.1, 0.1, 0.1],
+            // More than 300 ms below 0.35
+            &[0.1, 0.1, 0.1],
+            &[0.4, 0.45, 0.4],
+        ]);
+        assert_eq!(
+            events,
+            vec![Silence, Silence, SpeechStarted, Speaking, Speaking, Speaking, Speaking, SilenceDetected, Silence]
+        );
+    }
+
+    #[test]
+    fn frames_without_a_window_reuse_the_last_probability() {
+        use VadEvent::*;
+        // Short frames completing no window, then a failed model reporting 0
+        let events = events(&[&[0.9], &[], &[], &[0.0], &[], &[], &[], &[]]);
+        assert_eq!(events, vec![Silence, SpeechStarted, Speaking, Speaking, Speaking, Speaking, SilenceDetected, Silence]);
+    }
+}
@@ -0,0 +1,342 @@
+use super::{stop_factor, Segmenter, Vad, VadEvent};
+use crate::config::VadConfig;
+use realfft::num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+use std::sync::Arc;
+
+/// Analysis block; long enough to resolve the harmonics of a low voice
+const BLOCK_MS: u32 = 30;
+/// The band carrying most speech energy, as on a telephone line
+const SPEECH_BAND_HZ: (f32, f32) = (300.0, 3400.0);
+/// Share of the block energy that must fall in the speech band. Hum and
+/// desk knocks have almost none; a low voice keeps a good part of its
+/// energy in the fundamental below the band.
+const MIN_BAND_RATIO: f32 = 0.3;
+/// Zero crossings per second outside which a block is hum (too few) or
+/// hiss and broadband noise (too many)
+const ZCR_RANGE: (f32, f32) = (150.0, 5000.0);
+/// Spectral flatness in the speech band above which a block is noise-like
+/// rather than harmonic. White noise is around 0.55, an impulse near 1.
+const MAX_FLATNESS: f32 = 0.35;
+/// Consecutive speech-like blocks before a frame counts as speech, so a
+/// single transient block cannot start an utterance
+const MIN_SPEECH_BLOCKS: u32 = 2;
+
+/// Speech is sustained, harmonic energy in the speech band: band-limited
+/// energy, zero-crossing rate and spectral flatness must all agree, which
+/// rejects clicks, knocks, typing and steady hum that fool an RMS threshold
+pub struct SpectralVad {
+    energy_threshold: f32,
+    stop_threshold: f32,
+    sample_rate: u32,
+    fft: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    // Samples of the block being filled; blocks span frame boundaries
+    pending: Vec<i16>,
+    speech_blocks: u32,
+    segmenter: Segmenter,
+}
+
+/// What one analysis block looks like
+#[derive(Debug)]
+struct Features {
+    band_rms: f32,
+    band_ratio: f32,
+    zcr: f32,
+    flatness: f32,
+}
+
+impl SpectralVad {
+    pub fn new(config: &VadConfig, sample_rate: u32, silence_threshold_ms: u64) -> Self {
+        let block_size = (sample_rate * BLOCK_MS / 1000) as usize;
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(block_size);
+        // Hann window, so leakage does not smear harmonics into a flat spectrum
+        let window = (0..block_size)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / block_size as f32).cos())
+            .collect();
+        Self {
+            energy_threshold: config.energy_threshold,
+            stop_threshold: config.energy_threshold * stop_factor(config.hysteresis_db),
+            sample_rate,
+            input: fft.make_input_vec(),
+            spectrum: fft.make_output_vec(),
+            fft,
+            window,
+            pending: Vec::with_capacity(block_size),
+            speech_blocks: 0,
+            segmenter: Segmenter::new(config, sample_rate, silence_threshold_ms),
+        }
+    }
+
+    fn analyze(&mut self, block: &[i16]) -> Features {
+        let samples = block.iter().map(|&s| s as f32 / i16::MAX as f32);
+        let rms = (samples.clone().map(|x| x * x).sum::<f32>() / block.len() as f32).sqrt();
+        let crossings = block
+            .windows(2)
+            .filter(|pair| (pair[0] >= 0) != (pair[1] >= 0))
+            .count();
+        let zcr = crossings as f32 * self.sample_rate as f32 / block.len() as f32;
+
+        for ((input, x), w) in self.input.iter_mut().zip(samples).zip(&self.window) {
+            *input = x * w;
+        }
+        if self.fft.process(&mut self.input, &mut self.spectrum).is_err() {
+            return Features { band_rms: 0.0, band_ratio: 0.0, zcr, flatness: 1.0 };
+        }
+
+        let bin_hz = self.sample_rate as f32 / block.len() as f32;
+        let band = (SPEECH_BAND_HZ.0 / bin_hz).ceil() as usize..=(SPEECH_BAND_HZ.1 / bin_hz) as usize;
+        let power: Vec<f32> = self.spectrum.iter().map(|c| c.norm_sqr()).collect();
+        let total = power.iter().sum::<f32>();
+        let band_power = &power[*band.start()..=(*band.end()).min(power.len() - 1)];
+        let in_band = band_power.iter().sum::<f32>();
+        if total <= f32::EPSILON || in_band <= f32::EPSILON {
+            return Features { band_rms: 0.0, band_ratio: 0.0, zcr, flatness: 1.0 };
+        }
+
+        // Geometric over arithmetic mean: near 0 for harmonics, near 1 for noise
+        let log_mean = band_power.iter().map(|p| (p + 1e-12).ln()).sum::<f32>() / band_power.len() as f32;
+        let flatness = log_mean.exp() / (in_band / band_power.len() as f32);
+
+        let band_ratio = in_band / total;
+        Features {
+            band_rms: rms * band_ratio.sqrt(),
+            band_ratio,
+            zcr,
+            flatness,
+        }
+    }
+
+    fn is_speech_like(&self, features: &Features) -> bool {
+        let threshold = if self.segmenter.is_speaking() { self.stop_threshold } else { self.energy_threshold };
+        features.band_rms > threshold
+            && features.band_ratio >= MIN_BAND_RATIO
+            && (ZCR_RANGE.0..=ZCR_RANGE.1).contains(&features.zcr)
+            && features.flatness <= MAX_FLATNESS
+    }
+}
+
+impl Vad for SpectralVad {
+    fn describe(&self) -> String {
+        format!(
+            "spectral ({:.0}-{:.0} Hz band threshold {})",
+            SPEECH_BAND_HZ.0, SPEECH_BAND_HZ.1, self.energy_threshold
+        )
+    }
+
+    fn process(&mut self, audio_chunk: &[i16]) -> VadEvent {
+        let block_size = self.window.len();
+        let mut is_speech = self.speech_blocks >= MIN_SPEECH_BLOCKS;
+        for &sample in audio_chunk {
+            self.pending.push(sample);
+            if self.pending.len() < block_size {
+                continue;
+            }
+
+            let block = std::mem::take(&mut self.pending);
+            let features = self.analyze(&block);
+            self.speech_blocks = if self.is_speech_like(&features) { self.speech_blocks + 1 } else { 0 };
+            is_speech |= self.speech_blocks >= MIN_SPEECH_BLOCKS;
+            // Hand the buffer back for the next block
+            self.pending = block;
+            self.pending.clear();
+        }
+        self.segmenter.update(is_speech, audio_chunk.len())
+    }
+
+    fn heard_speech(&self) -> bool {
+        self.segmenter.heard_speech()
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.speech_blocks = 0;
+        self.segmenter.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const RATE: u32 = 16000;
+
+    fn detector() -> SpectralVad {
+        SpectralVad::new(&Config::default().vad, RATE, 1000)
+    }
+
+    fn to_i16(signal: impl Iterator<Item = f32>) -> Vec<i16> {
+        signal.map(|x| (x * i16::MAX as f32) as i16).collect()
+    }
+
+    fn tone(freq: f32, amplitude: f32, seconds: f32) -> Vec<i16> {
+        to_i16(
+            (0..(RATE as f32 * seconds) as usize)
+                .map(|i| amplitude * (2.0 * std::f32::consts::PI * freq * i as f32 / RATE as f32).sin()),
+        )
+    }
+
+    fn white_noise(amplitude: f32, seconds: f32, seed: &mut u32) -> Vec<i16> {
+        to_i16((0..(RATE as f32 * seconds) as usize).map(|_| {
+            *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            amplitude * ((*seed >> 8) as f32 / (1 << 23) as f32 - 1.0)
+        }))
+    }
+
+    /// Run a signal through two-pole resonators at (frequency, bandwidth)
+    fn resonate(signal: &mut [f32], formants: &[(f32, f32)]) {
+        for &(freq, bandwidth) in formants {
+            let r = (-std::f32::consts::PI * bandwidth / RATE as f32).exp();
+            let theta = 2.0 * std::f32::consts::PI * freq / RATE as f32;
+            let (a1, a2) = (2.0 * r * theta.cos(), -r * r);
+            let (mut y1, mut y2) = (0.0, 0.0);
+            for x in signal.iter_mut() {
+                let y = *x + a1 * y1 + a2 * y2;
+                y2 = y1;
+                y1 = y;
+                *x = y;
+            }
+        }
+    }
+
+    fn normalize(mut signal: Vec<f32>, amplitude: f32) -> Vec<i16> {
+        let peak = signal.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        signal.iter_mut().for_each(|x| *x *= amplitude / peak);
+        to_i16(signal.into_iter())
+    }
+
+    /// A vowel: a glottal pulse train at `pitch` through formant resonators
+    fn vowel(pitch: f32, formants: &[(f32, f32)], amplitude: f32, seconds: f32) -> Vec<i16> {
+        let n = (RATE as f32 * seconds) as usize;
+        let period = RATE as f32 / pitch;
+        let mut signal: Vec<f32> = (0..n)
+            .map(|i| if (i as f32 % period) < 1.0 { 1.0 } else { 0.0 })
+            .collect();
+        resonate(&mut signal, formants);
+        normalize(signal, amplitude)
+    }
+
+    /// An unvoiced fricative: noise shaped by a broad resonance, e.g. around
+    /// 5 kHz for "s", 2.5 kHz for "sh"
+    fn fricative(center: f32, bandwidth: f32, amplitude: f32, seconds: f32, seed: &mut u32) -> Vec<i16> {
+        let mut signal: Vec<f32> = white_noise(1.0, seconds, seed).iter().map(|&s| s as f32 / i16::MAX as f32).collect();
+        resonate(&mut signal, &[(center, bandwidth)]);
+        normalize(signal, amplitude)
+    }
+
+    /// A 16 kHz mono file from tests/fixtures, see synthesize.py there
+    fn fixture(name: &str) -> Vec<i16> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+        let reader = hound::WavReader::open(path).unwrap();
+        assert_eq!((reader.spec().sample_rate, reader.spec().channels), (RATE, 1));
+        reader.into_samples::<i16>().map(Result::unwrap).collect()
+    }
+
+    fn events(vad: &mut SpectralVad, signal: &[i16]) -> Vec<VadEvent> {
+        signal.chunks(RATE as usize / 10).map(|frame| vad.process(frame)).collect()
+    }
+
+    fn triggers(signal: &[i16]) -> bool {
+        events(&mut detector(), signal).contains(&VadEvent::SpeechStarted)
+    }
+
+    #[test]
+    fn detects_voiced_speech_at_low_and_high_pitch() {
+        // "ah" spoken by a low and a high voice, and "ee"
+        assert!(triggers(&vowel(100.0, &[(700.0, 110.0), (1220.0, 120.0), (2600.0, 160.0)], 0.3, 0.5)));
+        assert!(triggers(&vowel(220.0, &[(850.0, 110.0), (1200.0, 120.0), (2800.0, 160.0)], 0.3, 0.5)));
+        assert!(triggers(&vowel(140.0, &[(300.0, 60.0), (2300.0, 140.0), (3000.0, 200.0)], 0.3, 0.5)));
+    }
+
+    #[test]
+    fn a_sentence_with_fricatives_is_one_utterance() {
+        // "She sells fish, sir": fricatives between voiced vowels with a
+        // falling pitch, short gaps at the word boundaries, then a pause
+        let mut seed = 11;
+        let ee = [(300.0, 60.0), (2300.0, 140.0), (3000.0, 200.0)];
+        let eh = [(550.0, 80.0), (1800.0, 120.0), (2500.0, 160.0)];
+        let ih = [(400.0, 70.0), (2000.0, 130.0), (2600.0, 160.0)];
+        let er = [(500.0, 80.0), (1350.0, 120.0), (1700.0, 160.0)];
+        let mut sentence = vec![0i16; RATE as usize / 2];
+        for part in [
+            fricative(2500.0, 1500.0, 0.08, 0.15, &mut seed),
+            vowel(180.0, &ee, 0.3, 0.18),
+            vec![0; RATE as usize / 20],
+            fricative(5500.0, 2500.0, 0.06, 0.14, &mut seed),
+            vowel(170.0, &eh, 0.3, 0.16),
+            vowel(165.0, &[(400.0, 80.0), (1000.0, 120.0), (2500.0, 160.0)], 0.2, 0.08),
+            fricative(5500.0, 2500.0, 0.06, 0.12, &mut seed),
+            vec![0; RATE as usize / 20],
+            fricative(4000.0, 4000.0, 0.04, 0.1, &mut seed),
+            vowel(155.0, &ih, 0.25, 0.12),
+            fricative(2500.0, 1500.0, 0.08, 0.16, &mut seed),
+            vec![0; RATE as usize / 10],
+            fricative(5500.0, 2500.0, 0.06, 0.12, &mut seed),
+            vowel(140.0, &er, 0.25, 0.25),
+        ] {
+            sentence.extend(part);
+        }
+        sentence.extend(vec![0; RATE as usize * 2]);
+
+        let events = events(&mut detector(), &sentence);
+        let started = events.iter().filter(|&&e| e == VadEvent::SpeechStarted).count();
+        let stopped: Vec<usize> = (0..events.len()).filter(|&i| events[i] == VadEvent::SilenceDetected).collect();
+        assert_eq!(started, 1);
+        // Open through the fricatives and gaps, closed only after the pause
+        assert_eq!(stopped.len(), 1);
+        assert!(stopped[0] >= 30, "closed at frame {}", stopped[0]);
+    }
+
+    #[test]
+    fn the_speech_fixture_is_one_utterance() {
+        let events = events(&mut detector(), &fixture("speech.wav"));
+        let count = |event| events.iter().filter(|&&e| e == event).count();
+        assert_eq!((count(VadEvent::SpeechStarted), count(VadEvent::SilenceDetected)), (1, 1));
+    }
+
+    #[test]
+    fn the_noise_fixtures_never_trigger() {
+        for name in ["keyboard.wav", "fan.wav"] {
+            assert!(!triggers(&fixture(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_hum_and_broadband_noise() {
+        // Loud mains hum with its first harmonic, far above the threshold
+        let hum: Vec<i16> = tone(50.0, 0.3, 1.0)
+            .iter()
+            .zip(tone(100.0, 0.15, 1.0))
+            .map(|(&a, b)| a + b)
+            .collect();
+        assert!(!triggers(&hum));
+
+        // A burst of hiss, as from a fan or breathing into the microphone
+        let mut seed = 3;
+        let mut signal = vec![0; RATE as usize / 2];
+        signal.extend(white_noise(0.3, 0.3, &mut seed));
+        assert!(!triggers(&signal));
+    }
+
+    #[test]
+    fn rejects_clicks_and_knocks() {
+        // Typing: 3 ms broadband clicks, several per second
+        let mut seed = 5;
+        let mut typing = vec![0i16; RATE as usize * 2];
+        for start in (0..typing.len() - 48).step_by(RATE as usize / 7) {
+            typing[start..start + 48].copy_from_slice(&white_noise(0.8, 0.003, &mut seed));
+        }
+        assert!(!triggers(&typing));
+
+        // A knock on the desk: a low thump decaying over 50 ms
+        let mut knock = vec![0i16; RATE as usize / 2];
+        knock.extend(to_i16((0..RATE as usize / 2).map(|i| {
+            let t = i as f32 / RATE as f32;
+            0.8 * (-t / 0.015).exp() * (2.0 * std::f32::consts::PI * 120.0 * t).sin()
+        })));
+        assert!(!triggers(&knock));
+    }
+}
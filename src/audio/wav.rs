@@ -0,0 +1,210 @@
+use super::downmix::{ChannelMix, Downmixer};
+use super::source::{AudioFrame, AudioSource, FrameFormat, FrameSender};
+use crate::config::Pacing;
+use hound::{SampleFormat, WavReader};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Plays a WAV file into the pipeline, in real time or as fast as possible
+pub struct WavFileSource {
+    path: PathBuf,
+    pacing: Pacing,
+    format: FrameFormat,
+    channel_mix: ChannelMix,
+}
+
+impl WavFileSource {
+    pub fn new(path: PathBuf, pacing: Pacing, format: FrameFormat, channel_mix: ChannelMix) -> Self {
+        Self {
+            path,
+            pacing,
+            format,
+            channel_mix,
+        }
+    }
+}
+
+impl AudioSource for WavFileSource {
+    fn describe(&self) -> String {
+        format!("WAV file {} ({:?} pacing)", self.path.display(), self.pacing)
+    }
+
+    fn format(&self) -> FrameFormat {
+        self.format
+    }
+
+    fn start(&mut self, tx: mpsc::UnboundedSender<AudioFrame>) -> Result<(), Box<dyn std::error::Error>> {
+        let reader = WavReader::open(&self.path)?;
+        let spec = reader.spec();
+        println!(
+            "[Audio] {}: {} Hz, {} channel(s), {} bit {:?}",
+            self.path.display(),
+            spec.sample_rate,
+            spec.channels,
+            spec.bits_per_sample,
+            spec.sample_format
+        );
+
+        let window = (spec.sample_rate as u64 * self.format.frame_ms as u64 / 1000) as usize;
+        let downmixer = Downmixer::new(self.channel_mix, spec.channels as usize, window)?;
+        if spec.channels > 1 {
+            println!("[Audio] Channel mix: {} of {} channel(s)", self.channel_mix, spec.channels);
+        }
+
+        let sender = FrameSender::new(spec.sample_rate, self.format, tx);
+        let pacing = self.pacing;
+
+        std::thread::Builder::new()
+            .name("wav-source".to_string())
+            .spawn(move || {
+                if let Err(e) = play(reader, downmixer, sender, pacing) {
+                    eprintln!("[Audio] WAV read error: {}", e);
+                }
+            })?;
+
+        Ok(())
+    }
+}
+
+fn play<R: std::io::Read>(
+    mut reader: WavReader<R>,
+    mut downmixer: Downmixer,
+    mut sender: FrameSender,
+    pacing: Pacing,
+) -> Result<(), hound::Error> {
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let bits = spec.bits_per_sample as u32;
+
+    // Everything is converted to 16-bit before downmixing
+    let samples: Box<dyn Iterator<Item = Result<i16, hound::Error>> + '_> = match spec.sample_format {
+        SampleFormat::Float => Box::new(
+            reader
+                .samples::<f32>()
+                .map(|s| s.map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)),
+        ),
+        SampleFormat::Int if bits > 16 => Box::new(
+            reader
+                .samples::<i32>()
+                .map(move |s| s.map(|s| (s >> (bits - 16)) as i16)),
+        ),
+        SampleFormat::Int => Box::new(
+            reader
+                .samples::<i32>()
+                .map(move |s| s.map(|s| (s << (16 - bits)) as i16)),
+        ),
+    };
+
+    // Read ~100ms of frames at a time
+    let block_frames = (spec.sample_rate / 10) as usize;
+    let mut block = Vec::with_capacity(block_frames * channels);
+    let mut mono = Vec::with_capacity(block_frames);
+    let mut frames_read: u64 = 0;
+    let started = Instant::now();
+
+    for sample in samples {
+        block.push(sample?);
+
+        if block.len() == block_frames * channels {
+            mono.clear();
+            downmixer.process(&block, &mut mono);
+            sender.push(&mono);
+            frames_read += mono.len() as u64;
+            block.clear();
+
+            if sender.is_closed() {
+                return Ok(());
+            }
+
+            if pacing == Pacing::Realtime {
+                let due = started + Duration::from_secs_f64(frames_read as f64 / spec.sample_rate as f64);
+                if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                    std::thread::sleep(wait);
+                }
+            }
+        }
+    }
+
+    mono.clear();
+    downmixer.process(&block, &mut mono);
+    sender.push(&mono);
+    sender.flush();
+    println!("[Audio] End of WAV file");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{WavSpec, WavWriter};
+    use std::io::Cursor;
+
+    const FORMAT: FrameFormat = FrameFormat { sample_rate: 16000, frame_ms: 100, channels: 1 };
+
+    /// Frames delivered for an in-memory WAV file
+    fn frames(spec: WavSpec, write: impl Fn(&mut WavWriter<&mut Cursor<Vec<u8>>>), mix: ChannelMix) -> Vec<AudioFrame> {
+        let mut file = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut file, spec).unwrap();
+        write(&mut writer);
+        writer.finalize().unwrap();
+
+        let reader = WavReader::new(Cursor::new(file.into_inner())).unwrap();
+        let window = (spec.sample_rate as u64 * FORMAT.frame_ms as u64 / 1000) as usize;
+        let downmixer = Downmixer::new(mix, spec.channels as usize, window).unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        play(reader, downmixer, FrameSender::new(spec.sample_rate, FORMAT, tx), Pacing::Fast).unwrap();
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    fn spec(channels: u16, sample_rate: u32, bits_per_sample: u16, sample_format: SampleFormat) -> WavSpec {
+        WavSpec { channels, sample_rate, bits_per_sample, sample_format }
+    }
+
+    #[test]
+    fn converts_8_and_24_bit_and_float_to_16_bit() {
+        // Half and minus a quarter of full scale, 1600 samples each
+        let eight = frames(
+            spec(1, 16000, 8, SampleFormat::Int),
+            |w| (0..1600).for_each(|i| w.write_sample(if i < 800 { 64i8 } else { -32 }).unwrap()),
+            ChannelMix::Average,
+        );
+        let twenty_four = frames(
+            spec(1, 16000, 24, SampleFormat::Int),
+            |w| (0..1600).for_each(|i| w.write_sample(if i < 800 { 0x40_0000 } else { -0x20_0000 }).unwrap()),
+            ChannelMix::Average,
+        );
+        let float = frames(
+            spec(1, 16000, 32, SampleFormat::Float),
+            |w| (0..1600).for_each(|i| w.write_sample(if i < 800 { 0.5f32 } else { -0.25 }).unwrap()),
+            ChannelMix::Average,
+        );
+
+        for frames in [eight, twenty_four, float] {
+            assert_eq!(frames.len(), 1);
+            let samples = &frames[0].samples;
+            assert!((samples[0] - 16384).abs() <= 1, "{}", samples[0]);
+            assert!((samples[1599] + 8192).abs() <= 1, "{}", samples[1599]);
+        }
+    }
+
+    #[test]
+    fn downmixes_stereo_and_resamples_to_the_frame_rate() {
+        // 300 ms of stereo at 48 kHz, the channels at different levels
+        let frames = frames(
+            spec(2, 48000, 16, SampleFormat::Int),
+            |w| {
+                for _ in 0..14400 {
+                    w.write_sample(1000i16).unwrap();
+                    w.write_sample(3000i16).unwrap();
+                }
+            },
+            ChannelMix::Average,
+        );
+
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|frame| frame.samples.len() == FORMAT.frame_size()));
+        assert_eq!(frames[2].timestamp, Duration::from_millis(200));
+        assert!((frames[1].samples[800] - 2000).abs() < 20, "{}", frames[1].samples[800]);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::audio::dsp::DspChain;
+use crate::audio::{downmix, source};
+use crate::config::{Config, SourceKind, VadKind};
+use crate::state::StateManager;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// How long the room and then the speech are measured
+const PHASE_SECONDS: u64 = 5;
+/// Input this far above the room's noise counts as speech while measuring
+const SPEECH_OVER_NOISE: f32 = 2.0;
+/// Shortest silence timeout proposed, however briskly the speaker talked
+const MIN_SILENCE_MS: u64 = 1500;
+const MAX_SILENCE_MS: u64 = 5000;
+
+/// Settings derived from a quiet and a speech recording
+#[derive(Debug, PartialEq)]
+pub struct Proposal {
+    pub noise_rms: f32,
+    /// 10th, 50th and 90th percentile of the speech frames' RMS
+    pub speech_rms: [f32; 3],
+    pub energy_threshold: f32,
+    pub longest_pause_ms: u64,
+    pub silence_threshold_ms: u64,
+}
+
+/// Measure the configured source, first the quiet room and then speech,
+/// propose `vad.energy_threshold` and `audio.silence_threshold_ms`, and save
+/// them to config.toml once confirmed (or right away with `yes`)
+pub async fn run(config: &Config, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    println!("=== VAD Calibration ===");
+
+    let mut audio_source = source::open(config, Arc::new(StateManager::new()))?;
+    println!("[Calibrate] Source: {}", audio_source.describe());
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    audio_source.start(tx)?;
+    let format = audio_source.format();
+    // Measure what the VAD will see: processed, and the louder meeting side
+    let mut dsp = DspChain::new(&config.dsp, format.sample_rate, format.channels);
+
+    println!("[Calibrate] Stay quiet for {} seconds...", PHASE_SECONDS);
+    let phase = format.sample_rate as u64 * PHASE_SECONDS;
+    let mut received: u64 = 0;
+    let mut quiet = Vec::new();
+    let mut speech = Vec::new();
+    let mut prompted = false;
+
+    while let Some(mut frame) = rx.recv().await {
+        dsp.process(&mut frame.samples);
+        let mono = if format.channels > 1 {
+            downmix::loudest_channel(&frame.samples, format.channels as usize)
+        } else {
+            frame.samples
+        };
+
+        if received < phase {
+            quiet.push(rms(&mono));
+        } else {
+            speech.push(rms(&mono));
+        }
+        received += mono.len() as u64;
+
+        if received >= phase && !prompted {
+            prompted = true;
+            println!("[Calibrate] Now speak normally for {} seconds, as you would dictate...", PHASE_SECONDS);
+        }
+        if received >= 2 * phase {
+            break;
+        }
+    }
+    drop(audio_source);
+
+    let proposal = propose(&quiet, &speech, format.frame_ms)?;
+    let db = |rms: f32| 20.0 * rms.max(1e-6).log10();
+    println!("[Calibrate] Room noise: {:.1} dBFS", db(proposal.noise_rms));
+    println!(
+        "[Calibrate] Speech: {:.1} / {:.1} / {:.1} dBFS (10th / 50th / 90th percentile)",
+        db(proposal.speech_rms[0]),
+        db(proposal.speech_rms[1]),
+        db(proposal.speech_rms[2])
+    );
+    println!("[Calibrate] Longest pause while speaking: {} ms", proposal.longest_pause_ms);
+    println!("[Calibrate] Proposed settings:");
+    println!("  vad.energy_threshold = {}", proposal.energy_threshold);
+    println!("  audio.silence_threshold_ms = {}", proposal.silence_threshold_ms);
+    if matches!(config.vad.kind, VadKind::Adaptive | VadKind::Neural) {
+        println!("  Note: vad.kind = {:?} does not use energy_threshold", config.vad.kind);
+    }
+
+    if !yes {
+        // Raw audio on stdin leaves no way to answer
+        if config.source.kind == SourceKind::Stdin {
+            println!("[Calibrate] Not saved; run again with --yes to save");
+            return Ok(());
+        }
+        print!("[Calibrate] Save to config.toml? [y/N] ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().lock().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            println!("[Calibrate] Not saved");
+            return Ok(());
+        }
+    }
+
+    // Start from the file, not from this run's config with its command line
+    // overrides, so only the tuned settings change
+    let mut saved = Config::load_or_create()?;
+    saved.vad.energy_threshold = proposal.energy_threshold;
+    saved.audio.silence_threshold_ms = proposal.silence_threshold_ms;
+    saved.save()?;
+    println!("[Calibrate] Saved to {:?}", Config::get_config_path()?);
+    Ok(())
+}
+
+/// Derive the settings from per-frame RMS levels of the quiet room and of
+/// speech. The threshold sits halfway in dB between the loud end of the
+/// noise and the quiet end of the speech; the silence timeout is twice the
+/// longest pause the speaker made.
+pub fn propose(quiet: &[f32], speech: &[f32], frame_ms: u32) -> Result<Proposal, String> {
+    if quiet.is_empty() || speech.is_empty() {
+        return Err("The input ended before both the quiet and the speech part were recorded".into());
+    }
+
+    let noise_rms = percentile(quiet, 0.95);
+    let speech_floor = noise_rms * SPEECH_OVER_NOISE;
+    let active: Vec<f32> = speech.iter().copied().filter(|&level| level > speech_floor).collect();
+    if active.len() * 5 < speech.len() {
+        return Err("Speech was not clearly louder than the room; move closer to the microphone or raise its gain".into());
+    }
+    let speech_rms = [percentile(&active, 0.1), percentile(&active, 0.5), percentile(&active, 0.9)];
+
+    let threshold = (noise_rms.max(1e-5) * speech_rms[0]).sqrt().max(speech_floor);
+    let energy_threshold = (threshold * 10_000.0).round() / 10_000.0;
+
+    // Pauses between the first and the last speech frame
+    let first = speech.iter().position(|&level| level > energy_threshold);
+    let last = speech.iter().rposition(|&level| level > energy_threshold);
+    let mut longest_pause = 0;
+    if let (Some(first), Some(last)) = (first, last) {
+        let mut pause = 0;
+        for &level in &speech[first..=last] {
+            pause = if level > energy_threshold { 0 } else { pause + 1 };
+            longest_pause = longest_pause.max(pause);
+        }
+    }
+    let longest_pause_ms = longest_pause as u64 * frame_ms as u64;
+    let silence_threshold_ms = (2 * longest_pause_ms).div_ceil(100) * 100;
+
+    Ok(Proposal {
+        noise_rms,
+        speech_rms,
+        energy_threshold,
+        longest_pause_ms,
+        silence_threshold_ms: silence_threshold_ms.clamp(MIN_SILENCE_MS, MAX_SILENCE_MS),
+    })
+}
+
+fn rms(samples: &[i16]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum = samples
+        .iter()
+        .map(|&s| {
+            let normalized = s as f32 / i16::MAX as f32;
+            normalized * normalized
+        })
+        .sum::<f32>();
+    (sum / samples.len() as f32).sqrt()
+}
+
+fn percentile(values: &[f32], fraction: f32) -> f32 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    sorted[((sorted.len() - 1) as f32 * fraction).round() as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold_sits_between_room_noise_and_speech() {
+        // 100 ms frames: a room at 0.002 RMS, then speech at 0.05-0.2 with a
+        // 900 ms pause in the middle
+        let quiet = vec![0.002; 50];
+        let mut speech = vec![0.002; 5];
+        speech.extend((0..20).map(|i| 0.05 + 0.0075 * i as f32));
+        speech.extend([0.003; 9]);
+        speech.extend([0.1; 16]);
+
+        let proposal = propose(&quiet, &speech, 100).unwrap();
+        assert_eq!(proposal.noise_rms, 0.002);
+        assert_eq!(proposal.speech_rms[0], 0.05 + 0.0075 * 4.0);
+        assert!(proposal.energy_threshold > 0.004 && proposal.energy_threshold < 0.05);
+        assert_eq!(proposal.longest_pause_ms, 900);
+        assert_eq!(proposal.silence_threshold_ms, 1800);
+    }
+
+    #[test]
+    fn refuses_speech_that_is_no_louder_than_the_room() {
+        assert!(propose(&[0.01; 50], &[0.012; 50], 100).is_err());
+        assert!(propose(&[0.01; 50], &[], 100).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::config::{Config, Pacing, SourceKind};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: dictation-rs [OPTIONS]
+
+Options:
+  --list-devices       List audio input devices and exit
+  --diagnostics        Show the input configuration and levels, then exit
+  --calibrate          Measure room noise and speech, propose VAD settings and offer to save them
+  --yes                Save calibrated settings without asking
+  --evaluate <DIR>     Score the VADs on labelled WAV files in DIR, then exit
+  --grid-search        With --evaluate, search for the best VAD thresholds
+  --source <KIND>      Audio source: microphone, wav, stdin, remote or meeting
+  --input <PATH>       WAV file to replay (implies --source wav)
+  --fast               Replay WAV input as fast as possible
+  --input-rate <HZ>    Sample rate of raw s16le stdin input
+  --bind <ADDR>        Remote microphone listen address (implies --source remote)
+  -h, --help           Show this help";
+
+/// Command line options, applied on top of config.toml
+#[derive(Debug, Default)]
+pub struct Args {
+    pub list_devices: bool,
+    pub diagnostics: bool,
+    pub calibrate: bool,
+    pub yes: bool,
+    pub evaluate: Option<PathBuf>,
+    pub grid_search: bool,
+    pub help: bool,
+    pub source: Option<SourceKind>,
+    pub input: Option<PathBuf>,
+    pub fast: bool,
+    pub input_rate: Option<u32>,
+    pub bind: Option<SocketAddr>,
+}
+
+impl Args {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut parsed = Args::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--list-devices" => parsed.list_devices = true,
+                "--diagnostics" => parsed.diagnostics = true,
+                "--calibrate" => parsed.calibrate = true,
+                "--yes" => parsed.yes = true,
+                "--evaluate" => parsed.evaluate = Some(PathBuf::from(Self::value(&mut args, &arg)?)),
+                "--grid-search" => parsed.grid_search = true,
+                "-h" | "--help" => parsed.help = true,
+                "--fast" => parsed.fast = true,
+                "--source" => {
+                    parsed.source = Some(match Self::value(&mut args, &arg)?.as_str() {
+                        "microphone" | "mic" => SourceKind::Microphone,
+                        "wav" => SourceKind::Wav,
+                        "stdin" => SourceKind::Stdin,
+                        "remote" => SourceKind::Remote,
+                        "meeting" => SourceKind::Meeting,
+                        other => return Err(format!("Unknown source: {}", other)),
+                    });
+                }
+                "--input" => parsed.input = Some(PathBuf::from(Self::value(&mut args, &arg)?)),
+                "--input-rate" => {
+                    let value = Self::value(&mut args, &arg)?;
+                    parsed.input_rate = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&rate| rate > 0)
+                            .ok_or_else(|| format!("Invalid sample rate: {}", value))?,
+                    );
+                }
+                "--bind" => {
+                    let value = Self::value(&mut args, &arg)?;
+                    parsed.bind = Some(value.parse().map_err(|_| format!("Invalid address: {}", value))?);
+                }
+                other => return Err(format!("Unknown argument: {}", other)),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(path) = &self.input {
+            config.source.kind = SourceKind::Wav;
+            config.source.path = Some(path.clone());
+        }
+        if let Some(bind) = self.bind {
+            config.source.kind = SourceKind::Remote;
+            config.remote.bind = Some(bind);
+        }
+        if let Some(kind) = self.source {
+            config.source.kind = kind;
+        }
+        if self.fast {
+            config.source.pacing = Pacing::Fast;
+        }
+        if let Some(rate) = self.input_rate {
+            config.source.sample_rate = rate;
+        }
+    }
+
+    fn value<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> Result<String, String> {
+        args.next().ok_or_else(|| format!("{} requires a value", flag))
+    }
+}
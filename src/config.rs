@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -7,6 +8,16 @@ pub struct Config {
     pub deepgram: DeepgramConfig,
     pub audio: AudioConfig,
     pub vad: VadConfig,
+    #[serde(default)]
+    pub dsp: DspConfig,
+    #[serde(default)]
+    pub recorder: RecorderConfig,
+    #[serde(default)]
+    pub source: SourceConfig,
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    #[serde(default)]
+    pub meeting: MeetingConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,17 +25,303 @@ pub struct DeepgramConfig {
     pub api_key: String,
     pub language: String,
     pub model: String,
+    #[serde(default)]
+    pub encoding: AudioEncoding,
+    #[serde(default = "default_opus_bitrate")]
+    pub opus_bitrate: u32,
+    #[serde(default)]
+    pub turn_detection: TurnDetection,
+    #[serde(default = "default_endpointing_ms")]
+    pub endpointing_ms: u32,
+    #[serde(default = "default_utterance_end_ms")]
+    pub utterance_end_ms: u32,
+    #[serde(default)]
+    pub max_pause_ms: Option<u64>,
+}
+
+impl DeepgramConfig {
+    /// Longest pause sent as audio: `max_pause_ms`, or by default 1500 ms,
+    /// and with Deepgram turn detection at least `utterance_end_ms`, which
+    /// Deepgram times on the audio it receives
+    pub fn max_pause_ms(&self) -> u64 {
+        self.max_pause_ms.unwrap_or_else(|| match self.turn_detection {
+            TurnDetection::Local => default_max_pause_ms(),
+            TurnDetection::Deepgram => default_max_pause_ms().max(self.utterance_end_ms as u64),
+        })
+    }
+}
+
+/// How audio is encoded on the way to Deepgram
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AudioEncoding {
+    #[default]
+    Linear16,
+    Opus,
+    OggOpus,
+}
+
+/// What decides that an utterance is over and the stream can close
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TurnDetection {
+    /// The local VAD's silence timer
+    #[default]
+    Local,
+    /// Deepgram's endpointing and UtteranceEnd events; the local VAD only
+    /// opens the stream
+    Deepgram,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioConfig {
     pub silence_threshold_ms: u64,
     pub duck_volume: f32,
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: u32,
+    #[serde(default = "default_preroll_ms")]
+    pub preroll_ms: u64,
+    #[serde(default = "default_frame_ms")]
+    pub frame_ms: u32,
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default = "default_stall_timeout_ms")]
+    pub stall_timeout_ms: u64,
+    #[serde(default)]
+    pub device_sample_rate: Option<u32>,
+    #[serde(default)]
+    pub device_channels: Option<u16>,
+    #[serde(default)]
+    pub device_format: Option<String>,
+    #[serde(default = "default_channel_mix")]
+    pub channel_mix: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VadConfig {
+    #[serde(default)]
+    pub kind: VadKind,
     pub energy_threshold: f32,
+    #[serde(default = "default_margin_db")]
+    pub margin_db: f32,
+    #[serde(default = "default_attack_ms")]
+    pub attack_ms: u64,
+    #[serde(default = "default_release_ms")]
+    pub release_ms: u64,
+    #[serde(default = "default_min_speech_ms")]
+    pub min_speech_ms: u64,
+    #[serde(default = "default_hangover_ms")]
+    pub hangover_ms: u64,
+    #[serde(default = "default_hysteresis_db")]
+    pub hysteresis_db: f32,
+    #[serde(default)]
+    pub model_path: Option<PathBuf>,
+    #[serde(default = "default_start_probability")]
+    pub start_probability: f32,
+    #[serde(default = "default_stop_probability")]
+    pub stop_probability: f32,
+}
+
+/// Which voice activity detector decides when speech starts and ends
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VadKind {
+    /// Fixed RMS threshold
+    #[default]
+    Energy,
+    /// Margin over a continuously estimated noise floor
+    Adaptive,
+    /// Speech-band energy, zero-crossing rate and spectral flatness
+    Spectral,
+    /// Speech probability from an ONNX model (neural feature)
+    Neural,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DspConfig {
+    pub high_pass: bool,
+    pub high_pass_hz: f32,
+    pub noise_gate: bool,
+    pub gate_threshold_db: f32,
+    pub agc: bool,
+    pub agc_target_db: f32,
+    pub agc_max_gain_db: f32,
+}
+
+impl Default for DspConfig {
+    fn default() -> Self {
+        Self {
+            high_pass: false,
+            high_pass_hz: 80.0,
+            noise_gate: false,
+            gate_threshold_db: -50.0,
+            agc: false,
+            agc_target_db: -20.0,
+            agc_max_gain_db: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecorderConfig {
+    pub enabled: bool,
+    pub dir: Option<PathBuf>,
+    pub max_total_mb: u64,
+    pub max_age_days: u64,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            max_total_mb: 500,
+            max_age_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceConfig {
+    pub kind: SourceKind,
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub pacing: Pacing,
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    Microphone,
+    Wav,
+    Stdin,
+    Remote,
+    Meeting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pacing {
+    #[default]
+    Realtime,
+    Fast,
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        Self {
+            kind: SourceKind::Microphone,
+            path: None,
+            pacing: Pacing::Realtime,
+            sample_rate: default_sample_rate(),
+        }
+    }
+}
+
+/// Listener for the remote microphone source
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// Loopback or LAN address to listen on; never a default
+    pub bind: Option<SocketAddr>,
+    /// Pairing token; a random one is generated each run when unset
+    pub token: Option<String>,
+}
+
+/// Second input for the meeting source; the microphone comes from [audio]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeetingConfig {
+    /// Device carrying the other side of the call; unset follows the default
+    pub device: Option<String>,
+    /// Record what the device plays (an output device) rather than an input
+    pub loopback: bool,
+    pub me_label: String,
+    pub them_label: String,
+}
+
+impl Default for MeetingConfig {
+    fn default() -> Self {
+        Self {
+            device: None,
+            loopback: true,
+            me_label: "Me".to_string(),
+            them_label: "Them".to_string(),
+        }
+    }
+}
+
+fn default_sample_rate() -> u32 {
+    16000
+}
+
+fn default_opus_bitrate() -> u32 {
+    24000
+}
+
+fn default_endpointing_ms() -> u32 {
+    300
+}
+
+fn default_utterance_end_ms() -> u32 {
+    1000
+}
+
+fn default_max_pause_ms() -> u64 {
+    1500
+}
+
+fn default_margin_db() -> f32 {
+    12.0
+}
+
+fn default_attack_ms() -> u64 {
+    100
+}
+
+fn default_release_ms() -> u64 {
+    300
+}
+
+fn default_min_speech_ms() -> u64 {
+    200
+}
+
+fn default_hangover_ms() -> u64 {
+    100
+}
+
+fn default_hysteresis_db() -> f32 {
+    3.0
+}
+
+fn default_start_probability() -> f32 {
+    0.5
+}
+
+fn default_stop_probability() -> f32 {
+    0.35
+}
+
+fn default_preroll_ms() -> u64 {
+    400
+}
+
+fn default_frame_ms() -> u32 {
+    100
+}
+
+fn default_channel_mix() -> String {
+    "average".to_string()
+}
+
+fn default_stall_timeout_ms() -> u64 {
+    2000
 }
 
 impl Default for Config {
@@ -34,14 +331,44 @@ impl Default for Config {
                 api_key: String::new(),
                 language: "en".to_string(),
                 model: "nova-2".to_string(),
+                encoding: AudioEncoding::default(),
+                opus_bitrate: default_opus_bitrate(),
+                turn_detection: TurnDetection::default(),
+                endpointing_ms: default_endpointing_ms(),
+                utterance_end_ms: default_utterance_end_ms(),
+                max_pause_ms: None,
             },
             audio: AudioConfig {
                 silence_threshold_ms: 3000,
                 duck_volume: 0.2,
+                sample_rate: default_sample_rate(),
+                preroll_ms: default_preroll_ms(),
+                frame_ms: default_frame_ms(),
+                device: None,
+                stall_timeout_ms: default_stall_timeout_ms(),
+                device_sample_rate: None,
+                device_channels: None,
+                device_format: None,
+                channel_mix: default_channel_mix(),
             },
             vad: VadConfig {
+                kind: VadKind::default(),
                 energy_threshold: 0.02,
+                margin_db: default_margin_db(),
+                attack_ms: default_attack_ms(),
+                release_ms: default_release_ms(),
+                min_speech_ms: default_min_speech_ms(),
+                hangover_ms: default_hangover_ms(),
+                hysteresis_db: default_hysteresis_db(),
+                model_path: None,
+                start_probability: default_start_probability(),
+                stop_probability: default_stop_probability(),
             },
+            dsp: DspConfig::default(),
+            recorder: RecorderConfig::default(),
+            source: SourceConfig::default(),
+            remote: RemoteConfig::default(),
+            meeting: MeetingConfig::default(),
         }
     }
 }
@@ -52,7 +379,8 @@ impl Config {
 
         if config_path.exists() {
             let content = fs::read_to_string(&config_path)?;
-            let config: Config = toml::from_str(&content)?;
+            let mut config: Config = toml::from_str(&content)?;
+            config.reconcile();
             config.validate()?;
             Ok(config)
         } else {
@@ -70,11 +398,64 @@ impl Config {
                  # Edit this file to configure the application\n\
                  # Get your Deepgram API key from: https://console.deepgram.com/\n\n\
                  {}\n\n\
+                 # Deepgram settings\n\
+                 # encoding: \"linear16\" (raw PCM), \"opus\" or \"ogg-opus\" to cut bandwidth (needs the opus build feature)\n\
+                 # opus_bitrate: Opus bitrate in bits per second\n\
+                 # turn_detection: What ends an utterance: \"local\" (VAD silence timer) or \"deepgram\"\n\
+                 #   (Deepgram's endpointing and UtteranceEnd events; the local VAD only opens the stream)\n\
+                 # endpointing_ms: Pause after which Deepgram finalizes a phrase, deepgram only\n\
+                 # utterance_end_ms: Gap after the last word after which Deepgram ends the utterance\n\
+                 #   and the stream closes (at least 1000), deepgram only\n\
+                 # max_pause_ms: Longest pause inside an utterance sent as audio; the rest is held back and\n\
+                 #   the stream kept open with KeepAlive messages (0 sends every pause in full). Unset: 1500,\n\
+                 #   or utterance_end_ms if longer with deepgram turn detection\n\n\
                  # Audio settings\n\
                  # silence_threshold_ms: Auto-pause after this many milliseconds of silence\n\
-                 # duck_volume: Volume level (0.0-1.0) for other apps during dictation\n\n\
+                 # duck_volume: Volume level (0.0-1.0) for other apps during dictation\n\
+                 # sample_rate: Rate (Hz) audio is resampled to before VAD and Deepgram\n\
+                 # preroll_ms: Audio kept from before speech is detected and sent first\n\
+                 # frame_ms: Duration of the frames passed to VAD and Deepgram, 10-100 ms and a whole number\n\
+                 #   of samples at sample_rate (e.g. 10, 20, 30 or 100)\n\
+                 # device: Input device name or part of it (run with --list-devices); unset follows the system default\n\
+                 # stall_timeout_ms: Reopen the microphone when it delivers no audio for this long (at least 500)\n\
+                 # device_sample_rate, device_channels, device_format: Optional stream settings to\n\
+                 #   request from the device instead of its default (e.g. 48000, 2, \"i32\")\n\
+                 # channel_mix: How multi-channel input becomes mono: \"average\", \"loudest\" or a channel number from 1\n\n\
                  # VAD settings\n\
-                 # energy_threshold: RMS threshold for speech detection (0.0-1.0)\n",
+                 # kind: Detector deciding when speech starts and ends: \"energy\" (fixed threshold)\n\
+                 #   \"adaptive\" (margin over the measured background noise, no tuning per room)\n\
+                 #   \"spectral\" (speech-band energy that is also voice-like, ignores clicks and hum)\n\
+                 #   or \"neural\" (a Silero-style ONNX model, needs the neural build feature)\n\
+                 # energy_threshold: RMS threshold for speech detection (0.0-1.0); spectral applies it\n\
+                 #   to the 300-3400 Hz speech band only\n\
+                 # margin_db: How far above the noise floor input must be to count as speech, adaptive only\n\
+                 # attack_ms, release_ms: How quickly the adaptive level rises and falls; a longer attack ignores clicks\n\
+                 # min_speech_ms: Speech must last this long before an utterance starts; lowered to preroll_ms if above it\n\
+                 # hangover_ms: Gaps in speech up to this long still count towards min_speech_ms\n\
+                 # hysteresis_db: Once speech started, it continues down to this many dB below the start threshold\n\
+                 # model_path: ONNX model file for the neural detector, e.g. Silero VAD (silero_vad.onnx)\n\
+                 # start_probability, stop_probability: Model speech probability that starts speech,\n\
+                 #   and below which it stops once started, neural only\n\n\
+                 # DSP settings, applied before VAD and Deepgram; each stage can be turned on separately\n\
+                 # high_pass, high_pass_hz: Remove DC offset and hum below this frequency\n\
+                 # noise_gate, gate_threshold_db: Mute input quieter than this level (dBFS)\n\
+                 # agc, agc_target_db, agc_max_gain_db: Steer the level towards the target (dBFS), boosting at most this much\n\n\
+                 # Recorder settings\n\
+                 # enabled: Save each utterance as WAV plus a JSON sidecar with its transcript\n\
+                 # dir: Where to save them (default: recordings in the local app data directory)\n\
+                 # max_total_mb, max_age_days: Oldest recordings are deleted past either limit\n\n\
+                 # Source settings\n\
+                 # kind: \"microphone\", \"wav\" (replay source.path), \"stdin\" (raw s16le mono), \"remote\"\n\
+                 #   or \"meeting\" (microphone plus [meeting] device as two labelled channels)\n\
+                 # pacing: \"realtime\" or \"fast\" for WAV replay\n\
+                 # sample_rate: Rate (Hz) of raw stdin input\n\n\
+                 # Remote microphone settings, for kind = \"remote\"\n\
+                 # bind: Loopback or LAN address to serve the capture page on, e.g. \"192.168.1.20:8765\"\n\
+                 # token: Pairing token (16+ characters); unset generates one per run, shown in the log\n\n\
+                 # Meeting settings, for kind = \"meeting\"\n\
+                 # device: The other side of the call; unset follows the default output (or input without loopback)\n\
+                 # loopback: Record what an output device plays (Windows); false to use an input device instead\n\
+                 # me_label, them_label: Prefixes for transcripts from the microphone and the other side\n",
                 toml_string
             );
 
@@ -90,10 +471,109 @@ impl Config {
         Ok(())
     }
 
+    /// Adjust settings that files written for older versions combine in a
+    /// way the current pipeline cannot honour
+    fn reconcile(&mut self) {
+        // The speech that had to last min_speech_ms before the utterance
+        // started is only sent if the pre-roll still holds it. Files from
+        // before min_speech_ms may have no pre-roll at all.
+        if self.vad.min_speech_ms > self.audio.preroll_ms {
+            eprintln!(
+                "[Config] Warning: vad.min_speech_ms ({} ms) exceeds audio.preroll_ms ({} ms), using {} ms; raise audio.preroll_ms to keep it",
+                self.vad.min_speech_ms, self.audio.preroll_ms, self.audio.preroll_ms
+            );
+            self.vad.min_speech_ms = self.audio.preroll_ms;
+        }
+    }
+
     pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
         if self.deepgram.api_key.is_empty() {
             return Err("API key not set. Please edit config.toml and add your Deepgram API key.".into());
         }
+        if !(8000..=48000).contains(&self.audio.sample_rate) {
+            return Err("audio.sample_rate must be between 8000 and 48000 Hz.".into());
+        }
+        if !(10..=100).contains(&self.audio.frame_ms)
+            || !(self.audio.sample_rate as u64 * self.audio.frame_ms as u64).is_multiple_of(1000)
+        {
+            return Err("audio.frame_ms must be 10-100 ms and a whole number of samples at audio.sample_rate.".into());
+        }
+        if self.source.kind == SourceKind::Stdin && !(1000..=384000).contains(&self.source.sample_rate) {
+            return Err("source.sample_rate must be between 1000 and 384000 Hz.".into());
+        }
+        // Shorter than a few health polls and a device that is merely slow to
+        // start would be reopened over and over
+        if !(500..=60000).contains(&self.audio.stall_timeout_ms) {
+            return Err("audio.stall_timeout_ms must be between 500 and 60000 ms.".into());
+        }
+        if self.source.kind == SourceKind::Meeting && self.deepgram.encoding != AudioEncoding::Linear16 {
+            return Err("The meeting source sends two channels, which needs deepgram.encoding = \"linear16\".".into());
+        }
+        if self.deepgram.encoding != AudioEncoding::Linear16 {
+            if !cfg!(feature = "opus") {
+                return Err("deepgram.encoding needs a build with the \"opus\" feature; use \"linear16\".".into());
+            }
+            if ![8000, 12000, 16000, 24000, 48000].contains(&self.audio.sample_rate) {
+                return Err("Opus encoding needs audio.sample_rate of 8000, 12000, 16000, 24000 or 48000 Hz.".into());
+            }
+            if !(6000..=128000).contains(&self.deepgram.opus_bitrate) {
+                return Err("deepgram.opus_bitrate must be between 6000 and 128000.".into());
+            }
+        }
+        if self.deepgram.turn_detection == TurnDetection::Deepgram {
+            if self.source.kind == SourceKind::Meeting {
+                return Err("deepgram.turn_detection = \"deepgram\" ends utterances per channel; use \"local\" for the meeting source.".into());
+            }
+            if !(10..=5000).contains(&self.deepgram.endpointing_ms) {
+                return Err("deepgram.endpointing_ms must be between 10 and 5000 ms.".into());
+            }
+            if !(1000..=5000).contains(&self.deepgram.utterance_end_ms) {
+                return Err("deepgram.utterance_end_ms must be between 1000 and 5000 ms.".into());
+            }
+            // UtteranceEnd is timed on the audio Deepgram receives
+            let max_pause_ms = self.deepgram.max_pause_ms();
+            if max_pause_ms != 0 && max_pause_ms < self.deepgram.utterance_end_ms as u64 {
+                return Err("deepgram.max_pause_ms must be 0 or at least deepgram.utterance_end_ms.".into());
+            }
+        }
+        if self.vad.min_speech_ms > 2000 || self.vad.hangover_ms > 1000 {
+            return Err("vad.min_speech_ms must be at most 2000 ms and vad.hangover_ms at most 1000 ms.".into());
+        }
+        if !(0.0..=20.0).contains(&self.vad.hysteresis_db) {
+            return Err("vad.hysteresis_db must be between 0 and 20 dB.".into());
+        }
+        if self.vad.kind == VadKind::Neural {
+            if !cfg!(feature = "neural") {
+                return Err("vad.kind = \"neural\" needs a build with the \"neural\" feature.".into());
+            }
+            if self.vad.model_path.is_none() {
+                return Err("vad.kind = \"neural\" needs vad.model_path, e.g. a Silero VAD ONNX file.".into());
+            }
+            if ![8000, 16000].contains(&self.audio.sample_rate) {
+                return Err("The neural VAD needs audio.sample_rate of 8000 or 16000 Hz.".into());
+            }
+            let (start, stop) = (self.vad.start_probability, self.vad.stop_probability);
+            if !(0.0 < stop && stop <= start && start < 1.0) {
+                return Err("vad.start_probability and vad.stop_probability must be between 0 and 1, stop no higher than start.".into());
+            }
+        }
+        if self.vad.kind == VadKind::Adaptive {
+            if !(3.0..=40.0).contains(&self.vad.margin_db) {
+                return Err("vad.margin_db must be between 3 and 40 dB.".into());
+            }
+            if self.vad.hysteresis_db >= self.vad.margin_db {
+                return Err("vad.hysteresis_db must be below vad.margin_db.".into());
+            }
+            if !(1..=1000).contains(&self.vad.attack_ms) || !(1..=5000).contains(&self.vad.release_ms) {
+                return Err("vad.attack_ms must be 1-1000 ms and vad.release_ms 1-5000 ms.".into());
+            }
+        }
+        if self.dsp.high_pass && !(20.0..=1000.0).contains(&self.dsp.high_pass_hz) {
+            return Err("dsp.high_pass_hz must be between 20 and 1000 Hz.".into());
+        }
+        if self.dsp.agc && !(0.0..=40.0).contains(&self.dsp.agc_max_gain_db) {
+            return Err("dsp.agc_max_gain_db must be between 0 and 40 dB.".into());
+        }
         Ok(())
     }
 
@@ -1,32 +1,112 @@
-use super::types::TranscriptResponse;
+use super::types::StreamMessage;
+use crate::audio::uplink::UplinkEncoder;
+use crate::config::AudioEncoding;
 use futures_util::{SinkExt, StreamExt};
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 
+/// How long the stream may go without audio before a KeepAlive is sent;
+/// Deepgram closes a stream that receives nothing for 10 seconds
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(4);
+
 pub struct DeepgramClient {
     api_key: String,
     language: String,
     model: String,
+    sample_rate: u32,
+    encoding: AudioEncoding,
+    opus_bitrate: u32,
+    channel_labels: Vec<String>,
+    turn_detection: Option<TurnSettings>,
+}
+
+/// Deepgram's endpointing settings and where its turn events go
+struct TurnSettings {
+    endpointing_ms: u32,
+    utterance_end_ms: u32,
+    events: mpsc::UnboundedSender<TurnEvent>,
+}
+
+/// Turn-taking reported by Deepgram
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TurnEvent {
+    SpeechStarted,
+    /// A final result ended with a pause of `endpointing_ms`
+    Endpoint,
+    /// No words for `utterance_end_ms`: the utterance is over
+    UtteranceEnd,
 }
 
 impl DeepgramClient {
-    pub fn new(api_key: String, language: String, model: String) -> Self {
+    pub fn new(api_key: String, language: String, model: String, sample_rate: u32) -> Self {
         Self {
             api_key,
             language,
             model,
+            sample_rate,
+            encoding: AudioEncoding::Linear16,
+            opus_bitrate: 0,
+            channel_labels: Vec::new(),
+            turn_detection: None,
         }
     }
 
+    /// Send Opus instead of raw PCM
+    pub fn with_encoding(mut self, encoding: AudioEncoding, opus_bitrate: u32) -> Self {
+        self.encoding = encoding;
+        self.opus_bitrate = opus_bitrate;
+        self
+    }
+
+    /// Send interleaved audio with one channel per label, transcribed
+    /// separately and prefixed with the label of its channel
+    pub fn with_channel_labels(mut self, labels: Vec<String>) -> Self {
+        self.channel_labels = labels;
+        self
+    }
+
+    /// Ask Deepgram for endpointing, SpeechStarted and UtteranceEnd, and
+    /// report them on `events`
+    pub fn with_turn_detection(
+        mut self,
+        endpointing_ms: u32,
+        utterance_end_ms: u32,
+        events: mpsc::UnboundedSender<TurnEvent>,
+    ) -> Self {
+        self.turn_detection = Some(TurnSettings {
+            endpointing_ms,
+            utterance_end_ms,
+            events,
+        });
+        self
+    }
+
     pub async fn start_streaming(
         &self,
         mut audio_rx: mpsc::UnboundedReceiver<Vec<i16>>,
         text_tx: mpsc::UnboundedSender<String>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let url = format!(
-            "wss://api.deepgram.com/v1/listen?model={}&encoding=linear16&sample_rate=16000&channels=1&language={}",
-            self.model, self.language
+        let mut encoder = UplinkEncoder::new(self.encoding, self.sample_rate, self.opus_bitrate)?;
+        let channels = self.channel_labels.len().max(1);
+        let mut url = format!(
+            "wss://api.deepgram.com/v1/listen?model={}&channels={}&language={}",
+            self.model, channels, self.language
         );
+        if channels > 1 {
+            url.push_str("&multichannel=true");
+        }
+        if let Some(turns) = &self.turn_detection {
+            // UtteranceEnd works on word timings, which need interim results
+            url = format!(
+                "{}&interim_results=true&vad_events=true&endpointing={}&utterance_end_ms={}",
+                url, turns.endpointing_ms, turns.utterance_end_ms
+            );
+        }
+        let query = encoder.query();
+        if !query.is_empty() {
+            url = format!("{}&{}", url, query);
+        }
 
         let request = tokio_tungstenite::tungstenite::http::Request::builder()
             .uri(&url)
@@ -38,34 +118,76 @@ impl DeepgramClient {
         let (ws_stream, _) = connect_async(request).await?;
         let (mut write, mut read) = ws_stream.split();
 
-        println!("[Deepgram] Connected");
+        // Audio that arrived during the handshake is still queued on audio_rx
+        println!("[Deepgram] Connected ({} chunks buffered)", audio_rx.len());
 
         // Spawn task to send audio
         let send_task = tokio::spawn(async move {
-            while let Some(audio_chunk) = audio_rx.recv().await {
-                // Convert i16 to bytes (little-endian)
-                let bytes: Vec<u8> = audio_chunk
-                    .iter()
-                    .flat_map(|&sample| sample.to_le_bytes())
-                    .collect();
-
-                if write.send(Message::Binary(bytes)).await.is_err() {
-                    break;
+            loop {
+                // Pauses held back by the manager leave gaps in the audio
+                let audio_chunk = match tokio::time::timeout(KEEPALIVE_INTERVAL, audio_rx.recv()).await {
+                    Ok(Some(audio_chunk)) => audio_chunk,
+                    Ok(None) => break,
+                    Err(_) => {
+                        let keepalive = Message::Text(r#"{"type":"KeepAlive"}"#.to_string());
+                        if write.send(keepalive).await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                };
+                let messages = match encoder.encode(&audio_chunk) {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        eprintln!("[Deepgram] Encoding error: {}", e);
+                        return;
+                    }
+                };
+                for bytes in messages {
+                    if write.send(Message::Binary(bytes)).await.is_err() {
+                        return;
+                    }
                 }
             }
+
+            // Flush audio the encoder still holds
+            for bytes in encoder.finish().unwrap_or_default() {
+                let _ = write.send(Message::Binary(bytes)).await;
+            }
+
+            // No more audio: ask Deepgram to flush final results and close
+            let _ = write
+                .send(Message::Text(r#"{"type":"CloseStream"}"#.to_string()))
+                .await;
         });
 
         // Receive transcripts
         while let Some(msg) = read.next().await {
             match msg {
-                Ok(Message::Text(text)) => {
-                    if let Ok(response) = serde_json::from_str::<TranscriptResponse>(&text) {
+                Ok(Message::Text(text)) => match serde_json::from_str::<StreamMessage>(&text) {
+                    Ok(StreamMessage::Results(response)) => {
                         if let Some(transcript) = response.get_text() {
+                            let transcript = match self.channel_labels.get(response.channel_number()) {
+                                Some(label) => format!("{}: {}", label, transcript),
+                                None => transcript.to_string(),
+                            };
                             println!("[Deepgram] Transcript: {}", transcript);
-                            let _ = text_tx.send(transcript.to_string());
+                            let _ = text_tx.send(transcript);
+                        }
+                        if response.speech_final {
+                            self.report(TurnEvent::Endpoint);
                         }
                     }
-                }
+                    Ok(StreamMessage::SpeechStarted { timestamp }) => {
+                        println!("[Deepgram] Speech started at {:.2}s", timestamp);
+                        self.report(TurnEvent::SpeechStarted);
+                    }
+                    Ok(StreamMessage::UtteranceEnd { last_word_end }) => {
+                        println!("[Deepgram] Utterance ended after the word at {:.2}s", last_word_end);
+                        self.report(TurnEvent::UtteranceEnd);
+                    }
+                    Ok(StreamMessage::Other) | Err(_) => {}
+                },
                 Ok(Message::Close(_)) => {
                     println!("[Deepgram] Connection closed");
                     break;
@@ -81,4 +203,10 @@ impl DeepgramClient {
         send_task.abort();
         Ok(())
     }
+
+    fn report(&self, event: TurnEvent) {
+        if let Some(turns) = &self.turn_detection {
+            let _ = turns.events.send(event);
+        }
+    }
 }
@@ -1,9 +1,35 @@
 use serde::Deserialize;
 
+/// A message on the live transcription socket, told apart by its "type"
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum StreamMessage {
+    Results(TranscriptResponse),
+    /// Speech began in the audio, sent with `vad_events=true`
+    SpeechStarted {
+        /// Seconds since the start of the stream
+        timestamp: f64,
+    },
+    /// No word followed the last one for `utterance_end_ms`
+    UtteranceEnd {
+        /// Seconds since the start of the stream
+        last_word_end: f64,
+    },
+    /// Metadata and anything newer we do not use
+    #[serde(other)]
+    Other,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TranscriptResponse {
     pub channel: Channel,
     pub is_final: bool,
+    /// Endpointing saw the speaker pause after this final result
+    #[serde(default)]
+    pub speech_final: bool,
+    /// `[channel, channel count]` for multichannel requests
+    #[serde(default)]
+    pub channel_index: Vec<usize>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -17,6 +43,11 @@ pub struct Alternative {
 }
 
 impl TranscriptResponse {
+    /// The audio channel this result is for, 0 unless multichannel
+    pub fn channel_number(&self) -> usize {
+        self.channel_index.first().copied().unwrap_or(0)
+    }
+
     pub fn get_text(&self) -> Option<&str> {
         if !self.is_final {
             return None;
@@ -29,3 +60,47 @@ impl TranscriptResponse {
             .filter(|s| !s.is_empty())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_channel_of_multichannel_results() {
+        let json = r#"{"type":"Results","channel_index":[1,2],"is_final":true,
+            "channel":{"alternatives":[{"transcript":"sounds good","confidence":0.98}]}}"#;
+        let response: TranscriptResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.channel_number(), 1);
+        assert_eq!(response.get_text(), Some("sounds good"));
+
+        let json = r#"{"is_final":false,"channel":{"alternatives":[{"transcript":"sou"}]}}"#;
+        let response: TranscriptResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.channel_number(), 0);
+        assert_eq!(response.get_text(), None);
+    }
+
+    #[test]
+    fn parses_turn_events_by_type() {
+        let json = r#"{"type":"SpeechStarted","channel":[0],"timestamp":0.38}"#;
+        assert!(matches!(
+            serde_json::from_str(json).unwrap(),
+            StreamMessage::SpeechStarted { timestamp } if timestamp == 0.38
+        ));
+
+        let json = r#"{"type":"UtteranceEnd","channel":[0,1],"last_word_end":2.395}"#;
+        assert!(matches!(
+            serde_json::from_str(json).unwrap(),
+            StreamMessage::UtteranceEnd { last_word_end } if last_word_end == 2.395
+        ));
+
+        let json = r#"{"type":"Results","is_final":true,"speech_final":true,
+            "channel":{"alternatives":[{"transcript":"done"}]}}"#;
+        let StreamMessage::Results(response) = serde_json::from_str(json).unwrap() else {
+            panic!("not a result");
+        };
+        assert!(response.speech_final);
+
+        let json = r#"{"type":"Metadata","request_id":"abc","duration":1.5}"#;
+        assert!(matches!(serde_json::from_str(json).unwrap(), StreamMessage::Other));
+    }
+}
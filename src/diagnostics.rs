@@ -0,0 +1,93 @@
+use crate::audio::dsp::DspChain;
+use crate::audio::meter::LevelMeter;
+use crate::audio::source;
+use crate::audio::vad;
+use crate::config::{Config, VadKind};
+use crate::state::StateManager;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// How long the input is metered before the summary
+const DIAGNOSTICS_SECONDS: u64 = 10;
+
+/// Open the configured source without Deepgram, print what was opened and
+/// the input levels once a second, then summarise
+pub async fn run(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    println!("=== Input Diagnostics ===");
+
+    let mut audio_source = source::open(config, Arc::new(StateManager::new()))?;
+    println!("[Diagnostics] Source: {}", audio_source.describe());
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    audio_source.start(tx)?;
+
+    let format = audio_source.format();
+    println!("[Diagnostics] Pipeline: {} Hz, {} ms frames", format.sample_rate, format.frame_ms);
+    println!("[Diagnostics] Channel mix: {}", config.audio.channel_mix);
+    println!(
+        "[Diagnostics] DSP: {}",
+        DspChain::new(&config.dsp, format.sample_rate, format.channels).describe()
+    );
+    println!("[Diagnostics] VAD: {}", vad::from_config(config)?.describe());
+    let vad_threshold_db = 20.0 * config.vad.energy_threshold.log10();
+    if config.vad.kind == VadKind::Energy {
+        println!(
+            "[Diagnostics] VAD threshold: {:.1} dBFS (vad.energy_threshold = {})",
+            vad_threshold_db, config.vad.energy_threshold
+        );
+    }
+    println!("[Diagnostics] Speak normally for {} seconds...", DIAGNOSTICS_SECONDS);
+
+    let mut meter = LevelMeter::new(format.sample_rate, format.channels);
+    let total = format.sample_rate as u64 * DIAGNOSTICS_SECONDS;
+    let mut received: u64 = 0;
+    let mut loudest_rms_db = f32::NEG_INFINITY;
+    let mut warnings = 0;
+
+    while let Some(frame) = rx.recv().await {
+        if let Some(alert) = meter.process(&frame.samples) {
+            println!("[Diagnostics] Warning: {}", alert);
+            warnings += 1;
+        }
+
+        // One line per completed second
+        let before = received / format.sample_rate as u64;
+        received += (frame.samples.len() / format.channels as usize) as u64;
+        if received / format.sample_rate as u64 > before {
+            if let Some(levels) = meter.latest() {
+                println!("[Diagnostics] {:>3}s  {}", received / format.sample_rate as u64, levels);
+                loudest_rms_db = loudest_rms_db.max(levels.rms_db);
+            }
+        }
+
+        if received >= total {
+            break;
+        }
+    }
+    drop(audio_source);
+
+    let Some(levels) = meter.latest() else {
+        println!("[Diagnostics] Less than a second of audio was received");
+        return Ok(());
+    };
+
+    println!("[Diagnostics] Summary:");
+    println!("  Noise floor: {:.1} dBFS", levels.noise_floor_db);
+    println!("  Loudest second: {:.1} dBFS RMS", loudest_rms_db);
+    if config.vad.kind == VadKind::Adaptive {
+        // The threshold follows the floor, so only the margin can be wrong
+        if loudest_rms_db < levels.noise_floor_db + config.vad.margin_db {
+            println!("  Speech never rose vad.margin_db above the noise floor: move closer to the microphone or lower vad.margin_db");
+        } else if warnings == 0 {
+            println!("  Input looks fine");
+        }
+    } else if loudest_rms_db < vad_threshold_db {
+        println!("  Speech never reached the VAD threshold: raise the microphone gain or lower vad.energy_threshold");
+    } else if levels.noise_floor_db > vad_threshold_db {
+        println!("  Background noise is above the VAD threshold: raise vad.energy_threshold or enable the noise gate");
+    } else if warnings == 0 {
+        println!("  Input looks fine");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,434 @@
+use crate::audio::dsp::DspChain;
+use crate::audio::{downmix, source};
+use crate::audio::vad::{self, VadEvent};
+use crate::config::{Config, Pacing, SourceKind, VadKind};
+use crate::state::StateManager;
+use std::cmp::Ordering;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Grid searched for `vad.energy_threshold`, roughly 3 steps per 10 dB
+const ENERGY_THRESHOLDS: [f32; 10] = [0.005, 0.0075, 0.01, 0.015, 0.02, 0.03, 0.04, 0.05, 0.075, 0.1];
+/// Grid searched for `audio.silence_threshold_ms`
+const SILENCE_THRESHOLDS_MS: [u64; 6] = [500, 750, 1000, 1500, 2000, 3000];
+
+/// A labelled stretch of speech, in seconds from the start of the file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+}
+
+/// A WAV file as the VAD sees it, with its labels
+struct Clip {
+    frames: Vec<Vec<i16>>,
+    labels: Vec<Segment>,
+}
+
+/// Frame-level confusion counts and utterance-level timing, summed over files
+#[derive(Debug, Default, PartialEq)]
+pub struct Metrics {
+    pub true_positives: u64,
+    pub false_positives: u64,
+    pub false_negatives: u64,
+    /// Detected utterances overlapping no labelled speech
+    pub false_triggers: usize,
+    /// Labelled segments no detected utterance overlaps
+    pub missed: usize,
+    /// Labelled segments cut into more than one utterance
+    pub splits: usize,
+    pub segments: usize,
+    // Summed latencies and how many went into each sum
+    onset_ms: i64,
+    onsets: usize,
+    offset_ms: i64,
+    offsets: usize,
+}
+
+impl Metrics {
+    pub fn precision(&self) -> f64 {
+        ratio(self.true_positives, self.true_positives + self.false_positives)
+    }
+
+    pub fn recall(&self) -> f64 {
+        ratio(self.true_positives, self.true_positives + self.false_negatives)
+    }
+
+    pub fn f1(&self) -> f64 {
+        let (precision, recall) = (self.precision(), self.recall());
+        if precision + recall == 0.0 {
+            0.0
+        } else {
+            2.0 * precision * recall / (precision + recall)
+        }
+    }
+
+    /// F1 less the share of segments that were split, since cutting a
+    /// sentence in two ends the dictation in the middle of it, which costs
+    /// more than the few frames of the gap
+    pub fn rank_score(&self) -> f64 {
+        self.f1() - self.splits as f64 / self.segments.max(1) as f64
+    }
+
+    /// Mean time from the start of labelled speech to SpeechStarted
+    pub fn onset_latency_ms(&self) -> Option<i64> {
+        (self.onsets > 0).then(|| self.onset_ms / self.onsets as i64)
+    }
+
+    /// Mean time from the end of labelled speech to SilenceDetected
+    pub fn offset_latency_ms(&self) -> Option<i64> {
+        (self.offsets > 0).then(|| self.offset_ms / self.offsets as i64)
+    }
+
+    fn add(&mut self, other: &Metrics) {
+        self.true_positives += other.true_positives;
+        self.false_positives += other.false_positives;
+        self.false_negatives += other.false_negatives;
+        self.false_triggers += other.false_triggers;
+        self.missed += other.missed;
+        self.splits += other.splits;
+        self.segments += other.segments;
+        self.onset_ms += other.onset_ms;
+        self.onsets += other.onsets;
+        self.offset_ms += other.offset_ms;
+        self.offsets += other.offsets;
+    }
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+/// Run the detectors over every WAV file in `dir` that has a label file next
+/// to it, report how well they find the labelled speech, and with
+/// `grid_search` look for the best energy and silence thresholds
+pub async fn run(config: &Config, dir: &Path, grid_search: bool) -> Result<(), Box<dyn std::error::Error>> {
+    println!("=== VAD Evaluation ===");
+
+    let clips = load_clips(config, dir).await?;
+    if clips.is_empty() {
+        return Err(format!("No WAV files with label files in {}", dir.display()).into());
+    }
+    let frames: usize = clips.iter().map(|clip| clip.frames.len()).sum();
+    let seconds = frames as u64 * config.audio.frame_ms as u64 / 1000;
+    let segments: usize = clips.iter().map(|clip| clip.labels.len()).sum();
+    println!(
+        "[Evaluate] {} file(s), {}m {:02}s of audio, {} labelled segment(s)",
+        clips.len(),
+        seconds / 60,
+        seconds % 60,
+        segments
+    );
+
+    println!(
+        "{:<10} {:>9} {:>7} {:>6} {:>14} {:>7} {:>7} {:>9} {:>9}",
+        "Detector", "Precision", "Recall", "F1", "False triggers", "Missed", "Splits", "Onset", "Offset"
+    );
+    let mut kinds = vec![VadKind::Energy, VadKind::Adaptive, VadKind::Spectral];
+    if cfg!(feature = "neural") && config.vad.model_path.is_some() {
+        kinds.push(VadKind::Neural);
+    }
+    for kind in kinds {
+        let mut candidate = config.clone();
+        candidate.vad.kind = kind;
+        let metrics = evaluate(&candidate, &clips)?;
+        println!("{:<10} {}", format!("{:?}", kind).to_lowercase(), row(&metrics));
+    }
+
+    if grid_search {
+        search(config, &clips)?;
+    }
+    Ok(())
+}
+
+/// Try every pair of thresholds and print the best by `rank`
+fn search(config: &Config, clips: &[Clip]) -> Result<(), Box<dyn std::error::Error>> {
+    // Only these two detectors use energy_threshold
+    let kind = match config.vad.kind {
+        VadKind::Spectral => VadKind::Spectral,
+        _ => VadKind::Energy,
+    };
+    println!(
+        "[Evaluate] Grid search over energy_threshold and silence_threshold_ms ({:?} detector)...",
+        kind
+    );
+
+    let mut results = Vec::new();
+    for energy_threshold in ENERGY_THRESHOLDS {
+        for silence_threshold_ms in SILENCE_THRESHOLDS_MS {
+            let mut candidate = config.clone();
+            candidate.vad.kind = kind;
+            candidate.vad.energy_threshold = energy_threshold;
+            candidate.audio.silence_threshold_ms = silence_threshold_ms;
+            results.push((energy_threshold, silence_threshold_ms, evaluate(&candidate, clips)?));
+        }
+    }
+    results.sort_by(|a, b| rank(&a.2, &b.2));
+
+    println!(
+        "{:>9} {:>10} {:>9} {:>7} {:>6} {:>14} {:>7} {:>7} {:>9} {:>9}",
+        "Threshold", "Silence ms", "Precision", "Recall", "F1", "False triggers", "Missed", "Splits", "Onset", "Offset"
+    );
+    for (energy_threshold, silence_threshold_ms, metrics) in results.iter().take(5) {
+        println!("{:>9} {:>10} {}", energy_threshold, silence_threshold_ms, row(metrics));
+    }
+    let (energy_threshold, silence_threshold_ms, _) = &results[0];
+    println!(
+        "[Evaluate] Suggested: vad.energy_threshold = {}, audio.silence_threshold_ms = {}",
+        energy_threshold, silence_threshold_ms
+    );
+    Ok(())
+}
+
+/// Better results first: by `Metrics::rank_score`, then fewer false triggers
+fn rank(a: &Metrics, b: &Metrics) -> Ordering {
+    b.rank_score()
+        .total_cmp(&a.rank_score())
+        .then(a.false_triggers.cmp(&b.false_triggers))
+}
+
+fn row(metrics: &Metrics) -> String {
+    let latency = |ms: Option<i64>| ms.map_or("-".to_string(), |ms| format!("{:+} ms", ms));
+    format!(
+        "{:>9.3} {:>7.3} {:>6.3} {:>14} {:>7} {:>7} {:>9} {:>9}",
+        metrics.precision(),
+        metrics.recall(),
+        metrics.f1(),
+        metrics.false_triggers,
+        metrics.missed,
+        metrics.splits,
+        latency(metrics.onset_latency_ms()),
+        latency(metrics.offset_latency_ms())
+    )
+}
+
+/// Score the detector `config` selects over all clips
+fn evaluate(config: &Config, clips: &[Clip]) -> Result<Metrics, Box<dyn std::error::Error>> {
+    let mut total = Metrics::default();
+    for clip in clips {
+        let mut vad = vad::from_config(config)?;
+        let events: Vec<(VadEvent, bool)> = clip
+            .frames
+            .iter()
+            .map(|frame| (vad.process(frame), vad.heard_speech()))
+            .collect();
+        total.add(&score(&events, &clip.labels, config.audio.frame_ms));
+    }
+    Ok(total)
+}
+
+/// Compare one file's VAD events, each with whether its frame was speech,
+/// with its labels. A frame is labelled speech when its middle lies in a
+/// segment, and detected as speech from SpeechStarted to the last speech
+/// frame before SilenceDetected; the silence the VAD waits out before
+/// ending an utterance is not scored. Latencies are measured from the
+/// label to the end of the frame that raised the event, when it is known.
+pub fn score(events: &[(VadEvent, bool)], labels: &[Segment], frame_ms: u32) -> Metrics {
+    let frame = frame_ms as f64 / 1000.0;
+    let mut metrics = Metrics {
+        segments: labels.len(),
+        ..Metrics::default()
+    };
+
+    // Detected utterances: first frame, frame after the last speech, and the
+    // frame that ended it, if any
+    let mut detected: Vec<(usize, usize, Option<usize>)> = Vec::new();
+    let mut open: Option<(usize, usize)> = None;
+    for (i, &(event, speech)) in events.iter().enumerate() {
+        match event {
+            VadEvent::SpeechStarted => open = Some((i, i + 1)),
+            VadEvent::SilenceDetected => {
+                if let Some((start, end)) = open.take() {
+                    detected.push((start, end, Some(i)));
+                }
+            }
+            _ => {}
+        }
+        if let Some((_, end)) = &mut open {
+            if speech {
+                *end = i + 1;
+            }
+        }
+    }
+    if let Some((start, end)) = open {
+        detected.push((start, end, None));
+    }
+
+    let mut active = vec![false; events.len()];
+    for &(start, end, _) in &detected {
+        active[start..end].iter_mut().for_each(|frame| *frame = true);
+    }
+    for (i, &active) in active.iter().enumerate() {
+        let middle = (i as f64 + 0.5) * frame;
+        let labelled = labels.iter().any(|s| s.start <= middle && middle < s.end);
+        match (active, labelled) {
+            (true, true) => metrics.true_positives += 1,
+            (true, false) => metrics.false_positives += 1,
+            (false, true) => metrics.false_negatives += 1,
+            (false, false) => {}
+        }
+    }
+
+    let seconds = |frames: usize| frames as f64 * frame;
+    let overlaps = |&(start, end, _): &(usize, usize, Option<usize>), s: &Segment| {
+        seconds(start) < s.end && s.start < seconds(end)
+    };
+    for utterance in &detected {
+        let overlapped: Vec<&Segment> = labels.iter().filter(|s| overlaps(utterance, s)).collect();
+        match (overlapped.last(), utterance.2) {
+            (None, _) => metrics.false_triggers += 1,
+            (Some(last), Some(closed)) => {
+                metrics.offset_ms += ((seconds(closed + 1) - last.end) * 1000.0).round() as i64;
+                metrics.offsets += 1;
+            }
+            (Some(_), None) => {}
+        }
+    }
+    for segment in labels {
+        let mut covering = detected.iter().filter(|u| overlaps(u, segment));
+        let Some(utterance) = covering.next() else {
+            metrics.missed += 1;
+            continue;
+        };
+        if covering.next().is_some() {
+            metrics.splits += 1;
+        }
+        // Speech that an earlier utterance was still covering has no onset
+        if seconds(utterance.0 + 1) >= segment.start {
+            metrics.onset_ms += ((seconds(utterance.0 + 1) - segment.start) * 1000.0).round() as i64;
+            metrics.onsets += 1;
+        }
+    }
+
+    metrics
+}
+
+/// Parse a label file in Audacity's format: one segment per line as start
+/// and end in seconds, optionally followed by a label, which is ignored
+pub fn parse_labels(text: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace().map(str::parse::<f64>);
+        match (fields.next(), fields.next()) {
+            (Some(Ok(start)), Some(Ok(end))) if start <= end => segments.push(Segment { start, end }),
+            _ => return Err(format!("line {}: expected start and end seconds", number + 1)),
+        }
+    }
+    Ok(segments)
+}
+
+/// Load every WAV file in `dir` with a label file of the same name and a
+/// .txt extension, through the same resampling, channel mix and DSP as
+/// live input
+async fn load_clips(config: &Config, dir: &Path) -> Result<Vec<Clip>, Box<dyn std::error::Error>> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("wav")))
+        .collect();
+    paths.sort();
+
+    let mut clips = Vec::new();
+    for path in paths {
+        let label_path = path.with_extension("txt");
+        let Ok(text) = std::fs::read_to_string(&label_path) else {
+            println!("[Evaluate] Skipping {}: no {}", path.display(), label_path.display());
+            continue;
+        };
+        let labels = parse_labels(&text).map_err(|e| format!("{}: {}", label_path.display(), e))?;
+
+        let mut file_config = config.clone();
+        file_config.source.kind = SourceKind::Wav;
+        file_config.source.path = Some(path.clone());
+        file_config.source.pacing = Pacing::Fast;
+        let mut wav = source::open(&file_config, Arc::new(StateManager::new()))?;
+        let format = wav.format();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        wav.start(tx)?;
+
+        let mut dsp = DspChain::new(&config.dsp, format.sample_rate, format.channels);
+        let mut frames = Vec::new();
+        while let Some(mut frame) = rx.recv().await {
+            dsp.process(&mut frame.samples);
+            // The VAD hears the louder side of a stereo file, as it does live
+            frames.push(if format.channels > 1 {
+                downmix::loudest_channel(&frame.samples, format.channels as usize)
+            } else {
+                frame.samples
+            });
+        }
+        clips.push(Clip { frames, labels });
+    }
+    Ok(clips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use VadEvent::*;
+
+    #[test]
+    fn parses_audacity_labels() {
+        let labels = parse_labels("0.500000\t1.250000\thello\n\n# pause\n2\t3\n").unwrap();
+        assert_eq!(labels, [Segment { start: 0.5, end: 1.25 }, Segment { start: 2.0, end: 3.0 }]);
+        assert!(parse_labels("1.0\n").is_err());
+    }
+
+    #[test]
+    fn scores_frames_triggers_and_latency() {
+        // 100 ms frames: speech labelled at 0.2-0.6 s, heard from 0.3 s to
+        // 0.6 s and detected from the frame ending at 0.4 s; the silence
+        // after it ends with the frame ending at 0.9 s. A click at 1.5 s.
+        let labels = [Segment { start: 0.2, end: 0.6 }];
+        let mut events = vec![(Silence, false); 3];
+        events.push((SpeechStarted, true));
+        events.extend([(Speaking, true); 2]);
+        events.extend([(Speaking, false); 2]);
+        events.push((SilenceDetected, false));
+        events.extend([(Silence, false); 5]);
+        events.extend([(SpeechStarted, true), (SilenceDetected, false)]);
+
+        let metrics = score(&events, &labels, 100);
+        assert_eq!((metrics.true_positives, metrics.false_positives, metrics.false_negatives), (3, 1, 1));
+        assert_eq!(metrics.false_triggers, 1);
+        assert_eq!(metrics.missed, 0);
+        assert_eq!(metrics.splits, 0);
+        assert_eq!(metrics.onset_latency_ms(), Some(200));
+        assert_eq!(metrics.offset_latency_ms(), Some(300));
+    }
+
+    #[test]
+    fn a_pause_inside_a_segment_favours_the_longer_silence_threshold() {
+        // One labelled sentence with an 800 ms pause in the middle, 100 ms frames
+        let tone: Vec<i16> = (0..1600)
+            .map(|i| (8000.0 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 16000.0).sin()) as i16)
+            .collect();
+        let mut frames = vec![vec![0i16; 1600]; 5];
+        frames.extend(vec![tone.clone(); 8]);
+        frames.extend(vec![vec![0; 1600]; 8]);
+        frames.extend(vec![tone; 8]);
+        frames.extend(vec![vec![0; 1600]; 20]);
+        let clips = [Clip {
+            frames,
+            labels: vec![Segment { start: 0.5, end: 2.9 }],
+        }];
+
+        let metrics = |silence_threshold_ms| {
+            let mut config = Config::default();
+            config.audio.frame_ms = 100;
+            config.audio.silence_threshold_ms = silence_threshold_ms;
+            evaluate(&config, &clips).unwrap()
+        };
+        let (short, long) = (metrics(500), metrics(1000));
+        assert_eq!((short.splits, long.splits), (1, 0));
+        assert_eq!(rank(&long, &short), Ordering::Less);
+    }
+}
@@ -1,26 +1,57 @@
 mod audio;
+mod calibrate;
+mod cli;
 mod config;
 mod deepgram;
+mod diagnostics;
+mod evaluate;
+#[cfg(windows)]
 mod keyboard;
 mod state;
+#[cfg(windows)]
 mod tray;
 
-use audio::{capture::AudioCapture, ducking::AudioDucker, vad::{VoiceActivityDetector, VadEvent}};
-use config::Config;
-use deepgram::client::DeepgramClient;
+use audio::{device as audio_device, downmix, dsp::DspChain, ducking::AudioDucker, meter::{LevelMeter, LevelMonitor}, pause::PauseTrimmer, preroll::PreRollBuffer, recorder::{Recorder, Recording}, source::{self, AudioFrame, FrameFormat}, vad::{self, VadEvent}};
+use cli::Args;
+use config::{Config, SourceKind, TurnDetection};
+use deepgram::client::{DeepgramClient, TurnEvent};
+#[cfg(windows)]
 use keyboard::simulator::KeyboardSimulator;
 use state::{AppState, StateManager};
+#[cfg(windows)]
 use tray::icon::TrayManager;
 
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    if args.list_devices {
+        for device in audio_device::list_input_devices()? {
+            let marker = if device.is_default { " (default)" } else { "" };
+            println!("{}{}", device.name, marker);
+        }
+        return Ok(());
+    }
+
     println!("=== Dictation App Starting ===");
 
     // Load configuration
-    let config = match Config::load_or_create() {
+    let mut config = match Config::load_or_create() {
         Ok(cfg) => {
             println!("[Config] Loaded from: {:?}", Config::get_config_path()?);
             cfg
@@ -32,40 +63,90 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             return Err(e);
         }
     };
+    // Overrides can combine into settings the file alone would not allow
+    args.apply(&mut config);
+    if let Err(e) = config.validate() {
+        eprintln!("[Config] Error with command-line overrides: {}", e);
+        return Err(e);
+    }
+
+    if args.diagnostics {
+        return diagnostics::run(&config).await;
+    }
+    if args.calibrate {
+        return calibrate::run(&config, args.yes).await;
+    }
+    if let Some(dir) = &args.evaluate {
+        return evaluate::run(&config, dir, args.grid_search).await;
+    }
+
+    // Live dictation, local or remote, types into the focused window from the
+    // tray; replayed input runs headless and prints transcripts instead
+    let interactive = cfg!(windows) && matches!(config.source.kind, SourceKind::Microphone | SourceKind::Remote);
 
     let config = Arc::new(RwLock::new(config));
     let state_manager = Arc::new(StateManager::new());
+    let level_monitor = Arc::new(LevelMonitor::new());
 
     // Create channels
-    let (audio_tx, audio_rx) = mpsc::unbounded_channel::<Vec<i16>>();
+    let (audio_tx, audio_rx) = mpsc::unbounded_channel::<AudioFrame>();
     let (text_tx, text_rx) = mpsc::unbounded_channel::<String>();
 
-    // Start audio capture
-    println!("[Main] Starting audio capture...");
-    let _audio_capture = AudioCapture::start(audio_tx)?;
+    // Start audio source
+    let mut audio_source = source::open(&*config.read().await, state_manager.clone())?;
+    println!("[Main] Starting audio source: {}", audio_source.describe());
+    audio_source.start(audio_tx)?;
+    let format = audio_source.format();
+    println!("[Main] Pipeline: {} Hz, {} ms frames", format.sample_rate, format.frame_ms);
 
-    // Start keyboard simulator
-    println!("[Main] Starting keyboard simulator...");
-    tokio::spawn(async move {
-        KeyboardSimulator::start(text_rx).await;
+    // Start keyboard simulator, or print transcripts when headless
+    let output = tokio::spawn(async move {
+        #[cfg(windows)]
+        if interactive {
+            println!("[Main] Starting keyboard simulator...");
+            KeyboardSimulator::start(text_rx).await;
+            return;
+        }
+        print_transcripts(text_rx).await;
     });
 
     // Start VAD processor and Deepgram client manager
     let state_manager_clone = state_manager.clone();
     let config_clone = config.clone();
-    tokio::spawn(async move {
+    let level_monitor_clone = level_monitor.clone();
+    let manager = tokio::spawn(async move {
         vad_and_deepgram_manager(
             audio_rx,
             text_tx,
             state_manager_clone,
             config_clone,
+            level_monitor_clone,
+            format,
         )
         .await;
     });
 
+    if interactive {
+        #[cfg(windows)]
+        return run_tray(state_manager, config, level_monitor).await;
+    }
+
+    // Headless: run until the source is exhausted and the last transcript is out
+    let _ = manager.await;
+    let _ = output.await;
+    println!("[Main] Input finished");
+    Ok(())
+}
+
+#[cfg(windows)]
+async fn run_tray(
+    state_manager: Arc<StateManager>,
+    config: Arc<RwLock<Config>>,
+    level_monitor: Arc<LevelMonitor>,
+) -> Result<(), Box<dyn std::error::Error>> {
     // Create system tray
     println!("[Main] Creating system tray...");
-    let mut tray_manager = TrayManager::new(state_manager.clone(), config.clone())?;
+    let mut tray_manager = TrayManager::new(state_manager.clone(), config)?;
 
     println!("[Main] Dictation app ready!");
     println!("[Main] - Left-click tray icon to pause/resume");
@@ -77,41 +158,170 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         let current_state = state_manager.get().await;
         tray_manager.update_icon(current_state).await;
+        tray_manager.update_levels(level_monitor.latest());
 
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
     }
 }
 
+async fn print_transcripts(mut text_rx: mpsc::UnboundedReceiver<String>) {
+    while let Some(text) = text_rx.recv().await {
+        println!("[Transcript] {}", text);
+    }
+}
+
+/// Send a frame of the open utterance to Deepgram, less the long pauses,
+/// and record all of it, so recordings keep the stream's timeline
+fn forward(
+    recording: &mut Option<Recording>,
+    deepgram_tx: Option<&mpsc::UnboundedSender<Vec<i16>>>,
+    pauses: &mut PauseTrimmer,
+    speech: bool,
+    samples: Vec<i16>,
+) {
+    if let Some(tx) = deepgram_tx {
+        if let Some(recording) = recording {
+            recording.write(&samples);
+        }
+        if let Some(samples) = pauses.admit(samples, speech) {
+            let _ = tx.send(samples);
+        }
+    }
+}
+
+/// Add the silence held back from the utterance that just closed to the
+/// running total, and log both
+fn count_skipped(pauses: &mut PauseTrimmer, skipped_total: &mut Duration) {
+    let skipped = pauses.take_skipped();
+    if !skipped.is_zero() {
+        *skipped_total += skipped;
+        println!(
+            "[Deepgram] Held back {:.1}s of silence ({:.1}s since startup)",
+            skipped.as_secs_f64(),
+            skipped_total.as_secs_f64()
+        );
+    }
+}
+
 async fn vad_and_deepgram_manager(
-    mut audio_rx: mpsc::UnboundedReceiver<Vec<i16>>,
+    mut audio_rx: mpsc::UnboundedReceiver<AudioFrame>,
     text_tx: mpsc::UnboundedSender<String>,
     state_manager: Arc<StateManager>,
     config: Arc<RwLock<Config>>,
+    level_monitor: Arc<LevelMonitor>,
+    format: FrameFormat,
 ) {
     let cfg = config.read().await;
-    let mut vad = VoiceActivityDetector::new(
-        cfg.vad.energy_threshold,
-        cfg.audio.silence_threshold_ms,
-    );
-    let mut ducker = AudioDucker::new(cfg.audio.duck_volume);
+    let mut vad = vad::from_config(&cfg).unwrap_or_else(|e| {
+        eprintln!("[VAD] {}; using the energy detector", e);
+        Box::new(vad::EnergyVad::new(&cfg.vad, cfg.audio.sample_rate, cfg.audio.silence_threshold_ms))
+    });
+    println!("[VAD] Detector: {}", vad.describe());
+    // Ducking during a meeting would also turn down the other side
+    let meeting = format.channels > 1;
+    let mut ducker = AudioDucker::new(if meeting { 1.0 } else { cfg.audio.duck_volume });
+    let mut preroll = PreRollBuffer::new(format.sample_rate, format.channels, cfg.audio.preroll_ms);
+    let mut pauses = PauseTrimmer::new(format.sample_rate, format.channels, cfg.deepgram.max_pause_ms(), cfg.audio.preroll_ms);
+    // Silence held back from Deepgram since startup
+    let mut skipped_total = Duration::ZERO;
+    let mut meter = LevelMeter::new(format.sample_rate, format.channels);
+    let mut dsp = DspChain::new(&cfg.dsp, format.sample_rate, format.channels);
+    println!("[DSP] Processing: {}", dsp.describe());
+    let channel_labels = if meeting {
+        vec![cfg.meeting.me_label.clone(), cfg.meeting.them_label.clone()]
+    } else {
+        Vec::new()
+    };
+    let recorder = Recorder::from_config(&cfg.recorder, format.sample_rate, format.channels).unwrap_or_else(|e| {
+        eprintln!("[Recorder] Disabled: {}", e);
+        None
+    });
+    let deepgram_turns = cfg.deepgram.turn_detection == TurnDetection::Deepgram;
+    // Deepgram ends an utterance utterance_end_ms after the last word; the
+    // local timer takes over once that and the silence threshold have passed
+    let turn_timeout = Duration::from_millis(cfg.audio.silence_threshold_ms.max(cfg.deepgram.utterance_end_ms as u64));
+    let frame_duration = Duration::from_millis(format.frame_ms as u64);
     drop(cfg);
 
     let mut deepgram_handle: Option<tokio::task::JoinHandle<()>> = None;
     let mut deepgram_tx: Option<mpsc::UnboundedSender<Vec<i16>>> = None;
+    let mut recording: Option<Recording> = None;
+    let (turn_tx, mut turn_rx) = mpsc::unbounded_channel::<TurnEvent>();
+    // With Deepgram turn detection: whether Deepgram heard speech on the open
+    // stream, where the last speech the VAD heard ended, and by when the
+    // stream closes if Deepgram does not end the utterance
+    let mut deepgram_heard_speech = false;
+    let mut speech_ended_at = Duration::ZERO;
+    let mut close_by: Option<Duration> = None;
+
+    while let Some(mut frame) = audio_rx.recv().await {
+        // Meter the input as captured, before any processing
+        if let Some(alert) = meter.process(&frame.samples) {
+            eprintln!("[Meter] {}", alert);
+        }
+        if let Some(levels) = meter.latest() {
+            level_monitor.publish(levels);
+        }
+
+        // Keep filters settled even while paused
+        dsp.process(&mut frame.samples);
 
-    while let Some(audio_chunk) = audio_rx.recv().await {
         let current_state = state_manager.get().await;
 
         // Skip processing if paused
         if matches!(current_state, AppState::Paused | AppState::MicConflict) {
+            preroll.clear();
+
+            // Close a stream left open by a pause or a lost microphone
+            close_by = None;
+            if deepgram_tx.take().is_some() {
+                let _ = ducker.restore();
+                if let Some(handle) = deepgram_handle.take() {
+                    handle.abort();
+                }
+                count_skipped(&mut pauses, &mut skipped_total);
+                vad.reset();
+            }
+            if let (Some(recorder), Some(recording)) = (&recorder, recording.take()) {
+                recorder.finish(recording, "pause");
+            }
             continue;
         }
 
-        let vad_event = vad.process(&audio_chunk);
+        // Deepgram ends the utterance, or the local timer does when it stays
+        // silent about it, e.g. when it heard no words at all
+        let mut close = None;
+        while let Ok(event) = turn_rx.try_recv() {
+            match event {
+                _ if deepgram_tx.is_none() => {}
+                TurnEvent::SpeechStarted | TurnEvent::Endpoint => deepgram_heard_speech = true,
+                TurnEvent::UtteranceEnd => close = Some("utterance end"),
+            }
+        }
+        if close_by.is_some_and(|deadline| frame.timestamp >= deadline) {
+            println!("[Deepgram] No utterance end in time, closing the stream");
+            close = Some("silence");
+        }
+
+        // Either side of a meeting starts a stream, so the VAD hears the
+        // louder channel
+        let vad_event = if meeting {
+            vad.process(&downmix::loudest_channel(&frame.samples, format.channels as usize))
+        } else {
+            vad.process(&frame.samples)
+        };
+        if vad.heard_speech() {
+            speech_ended_at = frame.timestamp + frame_duration;
+        }
 
         match vad_event {
+            VadEvent::SpeechStarted if deepgram_tx.is_some() => {
+                // Speech resumed before Deepgram ended the utterance
+                close_by = None;
+                forward(&mut recording, deepgram_tx.as_ref(), &mut pauses, vad.heard_speech(), frame.samples);
+            }
             VadEvent::SpeechStarted => {
-                println!("[VAD] Speech started");
+                println!("[VAD] Speech started at {:.2}s", frame.timestamp.as_secs_f64());
                 state_manager.set(AppState::Speaking).await;
 
                 // Duck audio
@@ -123,11 +333,39 @@ async fn vad_and_deepgram_manager(
                     cfg.deepgram.api_key.clone(),
                     cfg.deepgram.language.clone(),
                     cfg.deepgram.model.clone(),
-                );
+                    format.sample_rate,
+                )
+                .with_encoding(cfg.deepgram.encoding, cfg.deepgram.opus_bitrate)
+                .with_channel_labels(channel_labels.clone());
+                let client = if deepgram_turns {
+                    client.with_turn_detection(cfg.deepgram.endpointing_ms, cfg.deepgram.utterance_end_ms, turn_tx.clone())
+                } else {
+                    client
+                };
+                // Turn events of earlier streams do not apply to this one
+                while turn_rx.try_recv().is_ok() {}
+                deepgram_heard_speech = false;
+
+                // Record the utterance from the start of the pre-roll
+                let preroll_audio = preroll.take();
+                let mut text_tx_clone = text_tx.clone();
+                if let Some(recorder) = &recorder {
+                    let preroll_frames = preroll_audio.len() / format.channels as usize;
+                    let preroll_duration = Duration::from_secs_f64(preroll_frames as f64 / format.sample_rate as f64);
+                    let start = frame.timestamp.saturating_sub(preroll_duration);
+                    match recorder.start(&cfg, start, frame.timestamp, text_tx.clone()) {
+                        Ok((mut started, tee_tx)) => {
+                            started.write(&preroll_audio);
+                            started.write(&frame.samples);
+                            recording = Some(started);
+                            text_tx_clone = tee_tx;
+                        }
+                        Err(e) => eprintln!("[Recorder] Failed to start recording: {}", e),
+                    }
+                }
                 drop(cfg);
 
                 let (dg_audio_tx, dg_audio_rx) = mpsc::unbounded_channel();
-                let text_tx_clone = text_tx.clone();
 
                 deepgram_handle = Some(tokio::spawn(async move {
                     if let Err(e) = client.start_streaming(dg_audio_rx, text_tx_clone).await {
@@ -135,36 +373,81 @@ async fn vad_and_deepgram_manager(
                     }
                 }));
 
-                // Send this chunk
-                let _ = dg_audio_tx.send(audio_chunk);
+                // Send the pre-roll, then this frame, both in frame-sized
+                // pieces. Audio sent before the handshake completes is queued
+                // on the channel, not dropped.
+                for piece in preroll_audio.chunks(format.frame_len()) {
+                    let _ = dg_audio_tx.send(piece.to_vec());
+                }
+                let _ = dg_audio_tx.send(frame.samples);
                 deepgram_tx = Some(dg_audio_tx);
             }
             VadEvent::Speaking => {
                 if matches!(current_state, AppState::Speaking) {
                     // Forward audio to Deepgram
-                    if let Some(ref tx) = deepgram_tx {
-                        let _ = tx.send(audio_chunk);
-                    }
+                    forward(&mut recording, deepgram_tx.as_ref(), &mut pauses, vad.heard_speech(), frame.samples);
                 }
             }
             VadEvent::SilenceDetected => {
-                println!("[VAD] Silence detected");
-                state_manager.set(AppState::AutoPaused).await;
-
-                // Restore audio
-                let _ = ducker.restore();
-
-                // Close Deepgram connection
-                deepgram_tx = None;
-                if let Some(handle) = deepgram_handle.take() {
-                    handle.abort();
+                println!("[VAD] Silence detected at {:.2}s", frame.timestamp.as_secs_f64());
+                if let Some(levels) = meter.latest() {
+                    println!("[Meter] {}", levels);
                 }
-
                 vad.reset();
+
+                let deadline = speech_ended_at + turn_timeout;
+                if deepgram_turns && deepgram_heard_speech && close.is_none() && frame.timestamp < deadline {
+                    // Deepgram needs the audio to time the gap after the last word
+                    println!("[VAD] Waiting for Deepgram to end the utterance");
+                    close_by = Some(deadline);
+                    forward(&mut recording, deepgram_tx.as_ref(), &mut pauses, vad.heard_speech(), frame.samples);
+                } else {
+                    close = Some("silence");
+                    preroll.push(&frame.samples);
+                }
+            }
+            VadEvent::Silence if deepgram_tx.is_some() => {
+                // Still waiting for Deepgram to end the utterance
+                forward(&mut recording, deepgram_tx.as_ref(), &mut pauses, vad.heard_speech(), frame.samples);
             }
             VadEvent::Silence => {
-                // Continue listening
+                // Continue listening, remembering the audio in case speech starts
+                preroll.push(&frame.samples);
+            }
+        }
+
+        if let Some(reason) = close {
+            state_manager.set(AppState::AutoPaused).await;
+
+            // Restore audio
+            let _ = ducker.restore();
+
+            // Close Deepgram connection
+            deepgram_tx = None;
+            if let Some(handle) = deepgram_handle.take() {
+                handle.abort();
+            }
+            count_skipped(&mut pauses, &mut skipped_total);
+            close_by = None;
+            if let (Some(recorder), Some(recording)) = (&recorder, recording.take()) {
+                recorder.finish(recording, reason);
             }
+
+            vad.reset();
         }
     }
+
+    // Input ended: closing the audio channel lets an open stream finalize
+    drop(deepgram_tx);
+    if let Some(handle) = deepgram_handle {
+        let _ = ducker.restore();
+        let _ = tokio::time::timeout(tokio::time::Duration::from_secs(10), handle).await;
+    }
+    if let (Some(recorder), Some(recording)) = (&recorder, recording) {
+        let _ = recorder.finish(recording, "end of input").await;
+    }
+    skipped_total += pauses.take_skipped();
+    if !skipped_total.is_zero() {
+        println!("[Deepgram] Held back {:.1}s of silence in total", skipped_total.as_secs_f64());
+    }
 }
@@ -1,3 +1,4 @@
+use crate::audio::meter::LevelSnapshot;
 use crate::config::Config;
 use crate::state::{AppState, StateManager};
 use std::sync::Arc;
@@ -13,6 +14,7 @@ pub struct TrayManager {
     pause_item: MenuItem,
     language_item: MenuItem,
     autostart_item: CheckMenuItem,
+    levels: Option<LevelSnapshot>,
     state_manager: Arc<StateManager>,
     config: Arc<tokio::sync::RwLock<Config>>,
 }
@@ -53,6 +55,7 @@ impl TrayManager {
             pause_item,
             language_item,
             autostart_item,
+            levels: None,
             state_manager,
             config,
         })
@@ -89,6 +92,24 @@ impl TrayManager {
         let _ = self.pause_item.set_text(pause_text);
     }
 
+    /// Show the input levels in the tooltip, so a muted or clipping
+    /// microphone is visible without opening a console
+    pub fn update_levels(&mut self, levels: Option<LevelSnapshot>) {
+        let Some(levels) = levels.filter(|l| Some(*l) != self.levels) else {
+            return;
+        };
+        self.levels = Some(levels);
+
+        let mut tooltip = format!(
+            "Dictation App\nInput {:.0} dBFS, peak {:.0} dBFS\nNoise floor {:.0} dBFS",
+            levels.rms_db, levels.peak_db, levels.noise_floor_db
+        );
+        if levels.clip_ratio > 0.0 {
+            tooltip.push_str(&format!("\nClipping {:.1}%", levels.clip_ratio * 100.0));
+        }
+        let _ = self.tray_icon.set_tooltip(Some(tooltip));
+    }
+
     async fn toggle_pause(&mut self) {
         let new_state = self.state_manager.toggle_pause().await;
         self.update_icon(new_state).await;
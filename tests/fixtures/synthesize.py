@@ -0,0 +1,194 @@
+"""Writes the VAD fixtures in this directory: 16 kHz mono 16-bit WAV.
+
+speech.wav is a source-filter rendering of "she sells fish, sir" in a quiet
+room: a jittered glottal pulse train with a falling pitch, formants gliding
+between vowels, shaped fricative noise, a stop burst, a short room tail
+and a -55 dBFS background. keyboard.wav is typing over the same background
+and fan.wav a desk fan with mains hum.
+
+Standard library only, seeded, so the files are reproducible:
+    python3 tests/fixtures/synthesize.py
+"""
+
+import math
+import os
+import random
+import struct
+import wave
+
+RATE = 16000
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+def silence(seconds):
+    return [0.0] * int(RATE * seconds)
+
+
+def background(seconds, rng, db=-55.0):
+    """Room noise, tilted towards the low end like most rooms"""
+    out, y = [], 0.0
+    gain = 10 ** (db / 20) * 3.0
+    for _ in range(int(RATE * seconds)):
+        y = 0.9 * y + 0.1 * rng.uniform(-1, 1)
+        out.append(gain * y)
+    return out
+
+
+class Resonator:
+    """Two-pole resonator whose frequency and bandwidth can move per sample"""
+
+    def __init__(self):
+        self.y1 = self.y2 = 0.0
+
+    def step(self, x, freq, bandwidth):
+        r = math.exp(-math.pi * bandwidth / RATE)
+        a1 = 2 * r * math.cos(2 * math.pi * freq / RATE)
+        a2 = -r * r
+        y = (1 - r) * x + a1 * self.y1 + a2 * self.y2
+        self.y2, self.y1 = self.y1, y
+        return y
+
+
+def envelope(i, n, ramp):
+    """Raised-cosine fade in and out over `ramp` samples"""
+    if i < ramp:
+        return 0.5 - 0.5 * math.cos(math.pi * i / ramp)
+    if i >= n - ramp:
+        return 0.5 - 0.5 * math.cos(math.pi * (n - 1 - i) / ramp)
+    return 1.0
+
+
+def voiced(seconds, pitch, formants, level, rng, state):
+    """Glottal pulses with jitter and shimmer through gliding formants.
+    `pitch` and each formant are (start, end) pairs."""
+    n = int(RATE * seconds)
+    resonators = state.setdefault("resonators", [Resonator() for _ in formants])
+    out, phase, amp = [], state.get("phase", 0.0), 1.0
+    for i in range(n):
+        t = i / n
+        f0 = pitch[0] + (pitch[1] - pitch[0]) * t
+        f0 *= 1 + 0.01 * math.sin(2 * math.pi * 5 * i / RATE)
+        phase += f0 / RATE
+        if phase >= 1.0:
+            phase -= 1.0 + rng.gauss(0, 0.01)
+            amp = 1 + rng.gauss(0, 0.05)
+        # Rosenberg pulse: open for 60% of the period, then closed
+        source = amp * (math.sin(math.pi * phase / 0.6) ** 2 if phase < 0.6 else 0.0)
+        x = source - state.get("previous", 0.0)
+        state["previous"] = source
+        for resonator, ((f1, f2), bw) in zip(resonators, formants):
+            x = resonator.step(x, f1 + (f2 - f1) * t, bw) * 2.0
+        out.append(x * envelope(i, n, int(0.02 * RATE)))
+    state["phase"] = phase
+    return normalize(out, level)
+
+
+def fricative(seconds, center, bandwidth, level, rng):
+    n = int(RATE * seconds)
+    resonator = Resonator()
+    out = [resonator.step(rng.uniform(-1, 1), center, bandwidth) for _ in range(n)]
+    out = [x * envelope(i, n, int(0.03 * RATE)) for i, x in enumerate(out)]
+    return normalize(out, level)
+
+
+def burst(level, rng):
+    """Release of a stop: a few milliseconds of decaying noise"""
+    n = int(0.012 * RATE)
+    return [level * rng.uniform(-1, 1) * math.exp(-i / (0.003 * RATE)) for i in range(n)]
+
+
+def normalize(signal, peak):
+    loudest = max(abs(x) for x in signal) or 1.0
+    return [x * peak / loudest for x in signal]
+
+
+def mix(base, signal, start):
+    for i, x in enumerate(signal):
+        if start + i < len(base):
+            base[start + i] += x
+
+
+def room(signal):
+    """A few early reflections, as from a desk and nearby walls"""
+    out = list(signal)
+    for delay_ms, gain in ((7, 0.35), (13, 0.25), (23, 0.15), (37, 0.08)):
+        delay = int(RATE * delay_ms / 1000)
+        for i in range(delay, len(out)):
+            out[i] += gain * signal[i - delay]
+    return out
+
+
+def sentence(rng):
+    ee = [(270, 300), (2300, 2250), (3000, 3000)]
+    eh = [(530, 560), (1850, 1750), (2500, 2500)]
+    ih = [(400, 420), (2000, 1900), (2600, 2550)]
+    er = [(500, 480), (1400, 1300), (1700, 1650)]
+    bandwidths = [70, 110, 170]
+    state = {}
+
+    def vowel(seconds, pitch, formants, level=0.3):
+        return voiced(seconds, pitch, list(zip(formants, bandwidths)), level, rng, state)
+
+    parts = [
+        fricative(0.14, 2600, 1200, 0.07, rng),  # sh
+        vowel(0.17, (195, 185), ee),
+        silence(0.04),
+        fricative(0.13, 5200, 2200, 0.05, rng),  # s
+        vowel(0.15, (185, 175), eh),
+        vowel(0.09, (175, 170), [(450, 400), (1000, 900), (2500, 2500)], 0.22),  # l
+        fricative(0.12, 5200, 2200, 0.05, rng),  # s
+        silence(0.05),
+        fricative(0.09, 4000, 3500, 0.035, rng),  # f
+        vowel(0.12, (170, 160), ih, 0.26),
+        fricative(0.15, 2600, 1200, 0.07, rng),  # sh
+        silence(0.12),  # comma
+        fricative(0.12, 5200, 2200, 0.05, rng),  # s
+        vowel(0.26, (160, 120), er, 0.26),
+    ]
+    speech = []
+    for part in parts:
+        speech.extend(part)
+    return room(speech)
+
+
+def write(name, signal):
+    frames = b"".join(struct.pack("<h", max(-32768, min(32767, int(x * 32767)))) for x in signal)
+    with wave.open(os.path.join(HERE, name), "wb") as out:
+        out.setnchannels(1)
+        out.setsampwidth(2)
+        out.setframerate(RATE)
+        out.writeframes(frames)
+
+
+def main():
+    rng = random.Random(2024)
+
+    # 0.8 s of room, the sentence, then 1.8 s of room
+    speech = sentence(rng)
+    signal = background(0.8 + len(speech) / RATE + 1.8, rng)
+    mix(signal, speech, int(0.8 * RATE))
+    write("speech.wav", signal)
+
+    # Typing: clicks of varying weight at an uneven pace
+    signal = background(4.0, rng)
+    at = int(0.3 * RATE)
+    while at < len(signal) - RATE // 10:
+        click = burst(rng.uniform(0.2, 0.6), rng)
+        mix(signal, click, at)
+        # The key bottoming out, then its release
+        mix(signal, burst(rng.uniform(0.1, 0.3), rng), at + int(rng.uniform(0.04, 0.09) * RATE))
+        at += int(rng.uniform(0.08, 0.3) * RATE)
+    write("keyboard.wav", signal)
+
+    # A fan: low rumble, blade hiss and 50 Hz hum
+    n = 4 * RATE
+    signal = background(4.0, rng, db=-40.0)
+    for i in range(n):
+        t = i / RATE
+        signal[i] += 0.08 * math.sin(2 * math.pi * 50 * t) + 0.03 * math.sin(2 * math.pi * 100 * t)
+        signal[i] += 0.02 * rng.uniform(-1, 1)
+    write("fan.wav", signal)
+
+
+if __name__ == "__main__":
+    main()